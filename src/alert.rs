@@ -0,0 +1,495 @@
+//! Rules that watch each symbol's `StockInfo` across polls and raise alerts
+//! when their condition changes.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{Indicators, SstraError, StockInfo};
+
+pub mod webhook;
+pub use webhook::{RetryPolicy, WebhookSink};
+
+/// A value a rule compares.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    /// The latest closing price.
+    Price,
+    /// The change in price over the period, as a percentage.
+    Change,
+    /// The relative strength index.
+    Rsi,
+    /// The simple moving average over this many days.
+    Sma(usize),
+    /// The exponential moving average over this many days.
+    Ema(usize),
+    /// The weighted moving average over this many days.
+    Wma(usize),
+    Value(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    /// Holds while the left operand is above the right one.
+    Above,
+    /// Holds while the left operand is below the right one.
+    Below,
+    /// Fires whenever the left operand moves from one side of the right one
+    /// to the other.
+    Crosses,
+}
+
+/// A condition on one symbol, e.g. "AAPL close below 150",
+/// "MSFT change % over 5" or "IBM price crosses 50d sma".
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub symbol: String,
+    pub left: Operand,
+    pub comparison: Comparison,
+    pub right: Operand,
+    /// The rule as it was written.
+    pub text: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// An above or below rule's condition started to hold.
+    Triggered,
+    /// An above or below rule's condition stopped holding.
+    Resolved,
+    CrossedAbove,
+    CrossedBelow,
+}
+
+/// A change in a rule's condition.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub symbol: String,
+    pub kind: AlertKind,
+    /// The values of the left and right operands.
+    pub value: f64,
+    pub threshold: f64,
+    pub time: DateTime<Utc>,
+}
+
+/// Anywhere alerts can be delivered to.
+#[async_trait(?Send)]
+pub trait AlertSink {
+    async fn send(&self, event: &AlertEvent) -> Result<(), SstraError>;
+}
+
+/// Writes each alert as a line of text to stderr.
+pub struct StderrSink;
+
+/// Appends each alert to a file as a line of JSON.
+pub struct FileSink {
+    path: PathBuf,
+}
+
+/// Keeps track of each rule's condition between polls, and delivers an alert
+/// to every sink whenever one changes.
+pub struct AlertEngine {
+    rules: Vec<Rule>,
+    /// Whether each rule's left operand was above its right one at the last
+    /// poll, if it was known.
+    states: Vec<Option<bool>>,
+    sinks: Vec<Box<dyn AlertSink>>,
+}
+
+impl FromStr for Operand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words[..] {
+            ["close"] | ["price"] => Ok(Operand::Price),
+            ["change"] | ["change", "%"] | ["change%"] => Ok(Operand::Change),
+            ["rsi"] => Ok(Operand::Rsi),
+            [days, average] => {
+                let days = days
+                    .strip_suffix('d')
+                    .and_then(|days| days.parse().ok())
+                    .filter(|&days| days > 0)
+                    .ok_or_else(|| format!("invalid window {}", days))?;
+                match average {
+                    "sma" | "avg" => Ok(Operand::Sma(days)),
+                    "ema" => Ok(Operand::Ema(days)),
+                    "wma" => Ok(Operand::Wma(days)),
+                    _ => Err(format!("unknown average {}", average)),
+                }
+            }
+            [value] => value
+                .parse()
+                .map(Operand::Value)
+                .map_err(|_| format!("unknown value {}", value)),
+            _ => Err(format!("unknown value {}", s)),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = SstraError;
+
+    /// Parses "<SYMBOL> <value> <above|over|below|under|crosses> <value>".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: String| SstraError::InvalidRule(format!("{}: {}", s, message));
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let position = words.iter().position(|word| {
+            matches!(
+                word.to_lowercase().as_str(),
+                "above" | "over" | "below" | "under" | "crosses"
+            )
+        });
+        let (symbol, left, comparison, right) = match (words.split_first(), position) {
+            (Some((symbol, _)), Some(position)) if position > 1 => (
+                symbol,
+                &words[1..position],
+                words[position],
+                &words[position + 1..],
+            ),
+            _ => {
+                return Err(invalid(String::from(
+                    "expected <symbol> <value> <above|below|crosses> <value>",
+                )))
+            }
+        };
+        let comparison = match comparison.to_lowercase().as_str() {
+            "above" | "over" => Comparison::Above,
+            "below" | "under" => Comparison::Below,
+            _ => Comparison::Crosses,
+        };
+        Ok(Rule {
+            symbol: symbol.to_uppercase(),
+            left: left.join(" ").parse().map_err(invalid)?,
+            comparison,
+            right: right.join(" ").parse().map_err(invalid)?,
+            text: s.trim().to_string(),
+        })
+    }
+}
+
+impl Operand {
+    /// The operand's value in `info`, if it was calculated.
+    fn value(&self, info: &StockInfo) -> Option<f64> {
+        let window = |averages: &[(usize, f64)], days: usize| {
+            averages
+                .iter()
+                .find(|(window, _)| *window == days)
+                .map(|(_, average)| *average)
+        };
+        match *self {
+            Operand::Price => Some(info.closing_price),
+            Operand::Change => Some(info.price_difference),
+            Operand::Rsi => info.relative_strength_index,
+            Operand::Sma(days) => window(&info.simple_moving_averages, days),
+            Operand::Ema(days) => window(&info.exponential_moving_averages, days),
+            Operand::Wma(days) => window(&info.weighted_moving_averages, days),
+            Operand::Value(value) => Some(value),
+        }
+    }
+
+    /// Adds whatever `indicators` need to calculate the operand.
+    fn require(&self, indicators: &mut Indicators) {
+        let add = |windows: &mut Vec<usize>, days: usize| {
+            if !windows.contains(&days) {
+                windows.push(days);
+            }
+        };
+        match *self {
+            Operand::Rsi if indicators.rsi_period.is_none() => indicators.rsi_period = Some(14),
+            Operand::Sma(days) => add(&mut indicators.sma_windows, days),
+            Operand::Ema(days) => add(&mut indicators.ema_windows, days),
+            Operand::Wma(days) => add(&mut indicators.wma_windows, days),
+            _ => {}
+        }
+    }
+}
+
+/// Reads one rule per line from a file, skipping empty lines and those
+/// starting with #.
+pub fn read_rules<P: Into<PathBuf>>(path: P) -> Result<Vec<Rule>, SstraError> {
+    fs::read_to_string(path.into())?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<Rule>, sinks: Vec<Box<dyn AlertSink>>) -> Self {
+        AlertEngine {
+            states: vec![None; rules.len()],
+            rules,
+            sinks,
+        }
+    }
+
+    /// The symbols the rules are about.
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = Vec::new();
+        for rule in &self.rules {
+            if !symbols.contains(&rule.symbol) {
+                symbols.push(rule.symbol.clone());
+            }
+        }
+        symbols
+    }
+
+    /// Adds whatever `indicators` need to evaluate every rule.
+    pub fn require(&self, indicators: &mut Indicators) {
+        for rule in &self.rules {
+            rule.left.require(indicators);
+            rule.right.require(indicators);
+        }
+    }
+
+    /// Evaluates the rules for `info`'s symbol, returning an event for every
+    /// rule whose condition changed since the last time.
+    ///
+    /// A rule isn't evaluated if either of its values is missing, and the
+    /// first evaluation of a rule only raises an alert if an above or below
+    /// condition already holds.
+    pub fn evaluate(&mut self, info: &StockInfo) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for (rule, state) in self.rules.iter().zip(self.states.iter_mut()) {
+            if !rule.symbol.eq_ignore_ascii_case(&info.symbol) {
+                continue;
+            }
+            let (value, threshold) = match (rule.left.value(info), rule.right.value(info)) {
+                (Some(value), Some(threshold)) => (value, threshold),
+                _ => continue,
+            };
+            // equal values don't change which side of the threshold it's on
+            let above = if value == threshold {
+                match *state {
+                    Some(above) => above,
+                    None => continue,
+                }
+            } else {
+                value > threshold
+            };
+            let previous = state.replace(above);
+            let kind = match (rule.comparison, previous, above) {
+                (_, Some(previous), above) if previous == above => continue,
+                (Comparison::Crosses, None, _) => continue,
+                (Comparison::Crosses, Some(_), true) => AlertKind::CrossedAbove,
+                (Comparison::Crosses, Some(_), false) => AlertKind::CrossedBelow,
+                (Comparison::Above, None, false) | (Comparison::Below, None, true) => continue,
+                (Comparison::Above, _, true) | (Comparison::Below, _, false) => {
+                    AlertKind::Triggered
+                }
+                (Comparison::Above, _, false) | (Comparison::Below, _, true) => AlertKind::Resolved,
+            };
+            events.push(AlertEvent {
+                rule: rule.text.clone(),
+                symbol: info.symbol.clone(),
+                kind,
+                value,
+                threshold,
+                time: Utc::now(),
+            });
+        }
+        events
+    }
+
+    /// Evaluates the rules for `info` and delivers any alerts to every sink.
+    ///
+    /// Every sink gets every alert even if another sink fails; the errors are
+    /// returned afterwards.
+    pub async fn process(&mut self, info: &StockInfo) -> Vec<SstraError> {
+        let mut errors = Vec::new();
+        for event in self.evaluate(info) {
+            for sink in &self.sinks {
+                if let Err(err) = sink.send(&event).await {
+                    errors.push(err);
+                }
+            }
+        }
+        errors
+    }
+}
+
+#[async_trait(?Send)]
+impl AlertSink for StderrSink {
+    async fn send(&self, event: &AlertEvent) -> Result<(), SstraError> {
+        eprintln!(
+            "{} {:?}: {} ({:.2} vs {:.2})",
+            event.time.format("%Y-%m-%dT%H:%M:%SZ"),
+            event.kind,
+            event.rule,
+            event.value,
+            event.threshold
+        );
+        Ok(())
+    }
+}
+
+impl FileSink {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileSink { path: path.into() }
+    }
+}
+
+#[async_trait(?Send)]
+impl AlertSink for FileSink {
+    async fn send(&self, event: &AlertEvent) -> Result<(), SstraError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let line = serde_json::to_string(event).map_err(std::io::Error::from)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn info(symbol: &str, closing_price: f64, sma: f64) -> StockInfo {
+        StockInfo {
+            simple_moving_averages: vec![(50, sma)],
+            ..testing::info(symbol, closing_price)
+        }
+    }
+
+    fn kinds(engine: &mut AlertEngine, info: &StockInfo) -> Vec<AlertKind> {
+        engine
+            .evaluate(info)
+            .into_iter()
+            .map(|event| event.kind)
+            .collect()
+    }
+
+    /// Remembers every alert it's sent.
+    struct RecordingSink(Rc<RefCell<Vec<AlertEvent>>>);
+
+    #[async_trait(?Send)]
+    impl AlertSink for RecordingSink {
+        async fn send(&self, event: &AlertEvent) -> Result<(), SstraError> {
+            self.0.borrow_mut().push(event.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert_eq!(
+            Rule {
+                symbol: String::from("AAPL"),
+                left: Operand::Price,
+                comparison: Comparison::Below,
+                right: Operand::Value(150.0),
+                text: String::from("aapl close below 150"),
+            },
+            "aapl close below 150".parse().unwrap()
+        );
+        let rule: Rule = "MSFT change % over 5".parse().unwrap();
+        assert_eq!(
+            (Operand::Change, Comparison::Above),
+            (rule.left, rule.comparison)
+        );
+        let rule: Rule = "IBM price crosses 50d SMA".parse().unwrap();
+        assert_eq!(
+            (Comparison::Crosses, Operand::Sma(50)),
+            (rule.comparison, rule.right)
+        );
+        assert!(matches!(
+            "IBM price near 50".parse::<Rule>(),
+            Err(SstraError::InvalidRule(_))
+        ));
+        assert!(matches!(
+            "IBM price above 0d sma".parse::<Rule>(),
+            Err(SstraError::InvalidRule(_))
+        ));
+    }
+
+    #[test]
+    fn requires_indicators() {
+        let rules = vec![
+            "IBM price crosses 20d sma".parse().unwrap(),
+            "IBM rsi above 70".parse().unwrap(),
+        ];
+        let engine = AlertEngine::new(rules, Vec::new());
+        let mut indicators = Indicators::default();
+        engine.require(&mut indicators);
+        assert_eq!(vec![30, 20], indicators.sma_windows);
+        assert_eq!(Some(14), indicators.rsi_period);
+    }
+
+    #[test]
+    fn alerts_on_threshold_transitions() {
+        let rules = vec!["AAPL close below 150".parse().unwrap()];
+        let mut engine = AlertEngine::new(rules, Vec::new());
+        assert_eq!(
+            vec![AlertKind::Triggered],
+            kinds(&mut engine, &info("AAPL", 140.0, 0.0))
+        );
+        assert!(kinds(&mut engine, &info("AAPL", 145.0, 0.0)).is_empty());
+        assert!(kinds(&mut engine, &info("MSFT", 160.0, 0.0)).is_empty());
+        assert_eq!(
+            vec![AlertKind::Resolved],
+            kinds(&mut engine, &info("AAPL", 160.0, 0.0))
+        );
+        assert!(kinds(&mut engine, &info("AAPL", 170.0, 0.0)).is_empty());
+
+        // a condition that doesn't hold at first isn't an alert
+        let rules = vec!["AAPL close below 150".parse().unwrap()];
+        let mut engine = AlertEngine::new(rules, Vec::new());
+        assert!(kinds(&mut engine, &info("AAPL", 160.0, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn alerts_on_crossings() {
+        let rules = vec!["IBM price crosses 50d sma".parse().unwrap()];
+        let mut engine = AlertEngine::new(rules, Vec::new());
+        assert!(kinds(&mut engine, &info("IBM", 100.0, 110.0)).is_empty());
+        assert!(kinds(&mut engine, &info("IBM", 110.0, 110.0)).is_empty());
+        assert_eq!(
+            vec![AlertKind::CrossedAbove],
+            kinds(&mut engine, &info("IBM", 111.0, 110.0))
+        );
+        assert_eq!(
+            vec![AlertKind::CrossedBelow],
+            kinds(&mut engine, &info("IBM", 100.0, 105.0))
+        );
+        // a missing average leaves the state alone
+        let mut missing = info("IBM", 120.0, 0.0);
+        missing.simple_moving_averages.clear();
+        assert!(kinds(&mut engine, &missing).is_empty());
+        assert!(kinds(&mut engine, &info("IBM", 100.0, 105.0)).is_empty());
+    }
+
+    #[test]
+    fn delivers_alerts_to_sinks() {
+        let path = testing::temp_path("alerts.ndjson");
+        let recorded = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = AlertEngine::new(
+            vec!["AAPL close below 150".parse().unwrap()],
+            vec![
+                Box::new(RecordingSink(Rc::clone(&recorded))),
+                Box::new(FileSink::new(&path)),
+            ],
+        );
+        let errors = tokio_test::block_on(engine.process(&info("AAPL", 140.0, 0.0)));
+        assert!(errors.is_empty());
+        assert_eq!(1, recorded.borrow().len());
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(1, contents.lines().count());
+        assert!(contents.contains(r#""kind":"triggered""#));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,398 @@
+//! Delivers alerts by POSTing JSON to a webhook.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::{json, Map, Value};
+
+use super::{AlertEvent, AlertSink};
+use crate::SstraError;
+
+/// The key of the template used for rules without one of their own.
+pub const DEFAULT_TEMPLATE: &str = "*";
+
+/// How often, and how long apart, failed deliveries are tried again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The number of attempts after the first one.
+    pub retries: u32,
+    /// The delay before the first retry, doubled before each later one.
+    pub backoff: Duration,
+    /// How long an attempt may take before it fails and is retried.
+    pub timeout: Duration,
+}
+
+/// POSTs every alert to a URL, retrying failures and recording the
+/// payloads that couldn't be delivered in a dead-letter file.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    /// The payload of each rule's alerts, by rule.
+    templates: HashMap<String, Value>,
+    retry: RetryPolicy,
+    dead_letter: Option<PathBuf>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Reads a JSON object of payload templates keyed by rule, with
+/// [`DEFAULT_TEMPLATE`] as the key of the one for every other rule.
+pub fn read_templates<P: Into<PathBuf>>(path: P) -> Result<HashMap<String, Value>, SstraError> {
+    let contents = fs::read_to_string(path.into())?;
+    serde_json::from_str(&contents)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into())
+}
+
+/// Fills in a template's placeholders, e.g. `{{symbol}}`, with the event's
+/// fields.
+///
+/// A string that is nothing but a placeholder is replaced by the field's JSON
+/// value, so `"{{value}}"` stays a number; placeholders inside longer strings
+/// are replaced by the field's text.
+pub fn render(template: &Value, event: &AlertEvent) -> Value {
+    let fields = match serde_json::to_value(event) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    };
+    fill(template, &fields)
+}
+
+fn fill(template: &Value, fields: &Map<String, Value>) -> Value {
+    match template {
+        Value::String(text) => {
+            let placeholder = text
+                .strip_prefix("{{")
+                .and_then(|text| text.strip_suffix("}}"))
+                .and_then(|name| fields.get(name.trim()));
+            if let Some(value) = placeholder {
+                return value.clone();
+            }
+            let mut text = text.clone();
+            for (name, value) in fields {
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                text = text.replace(&format!("{{{{{}}}}}", name), &value);
+            }
+            Value::String(text)
+        }
+        Value::Array(values) => {
+            Value::Array(values.iter().map(|value| fill(value, fields)).collect())
+        }
+        Value::Object(entries) => Value::Object(
+            entries
+                .iter()
+                .map(|(key, value)| (key.clone(), fill(value, fields)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+impl WebhookSink {
+    /// Without a template for a rule, its alerts are POSTed as they
+    /// serialize.
+    pub fn new<P: Into<PathBuf>>(
+        url: &str,
+        templates: HashMap<String, Value>,
+        retry: RetryPolicy,
+        dead_letter: Option<P>,
+    ) -> Result<Self, SstraError> {
+        let client = reqwest::Client::builder()
+            .timeout(retry.timeout)
+            .build()
+            .map_err(|err| SstraError::Delivery(err.to_string()))?;
+        Ok(WebhookSink {
+            client,
+            url: url.to_string(),
+            templates,
+            retry,
+            dead_letter: dead_letter.map(Into::into),
+        })
+    }
+
+    /// The payload of an event's alert.
+    pub fn payload(&self, event: &AlertEvent) -> Value {
+        match self
+            .templates
+            .get(&event.rule)
+            .or_else(|| self.templates.get(DEFAULT_TEMPLATE))
+        {
+            Some(template) => render(template, event),
+            None => serde_json::to_value(event).unwrap_or(Value::Null),
+        }
+    }
+
+    /// POSTs the payload once, returning whether a failure is worth trying
+    /// again along with the error.
+    async fn post(&self, payload: &Value) -> Result<(), (bool, String)> {
+        let response = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(payload.to_string())
+            .send()
+            .await
+            .map_err(|err| (true, err.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            // other client errors will fail the same way every time
+            let retry = status.is_server_error() || status.as_u16() == 429;
+            Err((retry, format!("the webhook responded {}", status)))
+        }
+    }
+
+    /// Appends a payload that couldn't be delivered to the dead-letter file.
+    fn bury(&self, payload: &Value, error: &str) -> Result<(), SstraError> {
+        let path = match &self.dead_letter {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let letter = json!({
+            "url": self.url,
+            "payload": payload,
+            "error": error,
+            "time": Utc::now(),
+        });
+        writeln!(file, "{}", letter)?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl AlertSink for WebhookSink {
+    async fn send(&self, event: &AlertEvent) -> Result<(), SstraError> {
+        let payload = self.payload(event);
+        let mut delay = self.retry.backoff;
+        let mut attempt = 0;
+        let error = loop {
+            match self.post(&payload).await {
+                Ok(()) => return Ok(()),
+                Err((true, _)) if attempt < self.retry.retries => {
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err((_, error)) => break error,
+            }
+        };
+        self.bury(&payload, &error)?;
+        Err(SstraError::Delivery(format!(
+            "Couldn't deliver the alert \"{}\" to {}: {}",
+            event.rule, self.url, error
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::AlertKind;
+    use crate::testing;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    fn event() -> AlertEvent {
+        AlertEvent {
+            rule: String::from("AAPL close below 150"),
+            symbol: String::from("AAPL"),
+            kind: AlertKind::Triggered,
+            value: 149.5,
+            threshold: 150.0,
+            time: Utc::now(),
+        }
+    }
+
+    fn retry() -> RetryPolicy {
+        RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Answers a request with each status in turn on a local port, returning
+    /// its URL and the bodies it received.
+    fn serve(statuses: Vec<u16>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim().to_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+            bodies
+        });
+        (url, server)
+    }
+
+    fn dead_letter(name: &str) -> PathBuf {
+        testing::temp_path(&format!("{}.ndjson", name))
+    }
+
+    #[test]
+    fn renders_templates() {
+        let template = json!({
+            "text": "{{symbol}}: {{rule}} ({{kind}})",
+            "price": "{{value}}",
+            "tags": ["sstra", "{{ symbol }}"],
+            "urgent": true,
+        });
+        assert_eq!(
+            json!({
+                "text": "AAPL: AAPL close below 150 (triggered)",
+                "price": 149.5,
+                "tags": ["sstra", "AAPL"],
+                "urgent": true,
+            }),
+            render(&template, &event())
+        );
+    }
+
+    #[test]
+    fn picks_templates_by_rule() {
+        let mut templates = HashMap::new();
+        templates.insert(
+            String::from(DEFAULT_TEMPLATE),
+            json!({ "text": "{{rule}}" }),
+        );
+        let sink = WebhookSink::new(
+            "http://localhost",
+            templates.clone(),
+            retry(),
+            None::<PathBuf>,
+        )
+        .unwrap();
+        assert_eq!(
+            json!({ "text": "AAPL close below 150" }),
+            sink.payload(&event())
+        );
+
+        templates.insert(
+            String::from("AAPL close below 150"),
+            json!({ "text": "buy" }),
+        );
+        let sink =
+            WebhookSink::new("http://localhost", templates, retry(), None::<PathBuf>).unwrap();
+        assert_eq!(json!({ "text": "buy" }), sink.payload(&event()));
+
+        let sink =
+            WebhookSink::new("http://localhost", HashMap::new(), retry(), None::<PathBuf>).unwrap();
+        assert_eq!(
+            Some(&json!("triggered")),
+            sink.payload(&event()).get("kind")
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_failed_deliveries() {
+        let (url, server) = serve(vec![500, 503, 200]);
+        let path = dead_letter("retried");
+        let sink = WebhookSink::new(&url, HashMap::new(), retry(), Some(&path)).unwrap();
+        assert!(sink.send(&event()).await.is_ok());
+        let bodies = server.join().unwrap();
+        assert_eq!(3, bodies.len());
+        let body: Value = serde_json::from_str(&bodies[2]).unwrap();
+        assert_eq!(Some(&json!("AAPL")), body.get("symbol"));
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn dead_letters_undeliverable_alerts() {
+        // a client error isn't retried
+        let (url, server) = serve(vec![400]);
+        let path = dead_letter("rejected");
+        let sink = WebhookSink::new(&url, HashMap::new(), retry(), Some(&path)).unwrap();
+        assert!(matches!(
+            sink.send(&event()).await,
+            Err(SstraError::Delivery(_))
+        ));
+        assert_eq!(1, server.join().unwrap().len());
+
+        let (url, server) = serve(vec![500, 500, 500]);
+        let sink = WebhookSink::new(&url, HashMap::new(), retry(), Some(&path)).unwrap();
+        assert!(sink.send(&event()).await.is_err());
+        assert_eq!(3, server.join().unwrap().len());
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let letters: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, letters.len());
+        assert_eq!(Some(&json!(url)), letters[1].get("url"));
+        assert_eq!(Some(&json!("AAPL")), letters[1]["payload"].get("symbol"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn times_out_webhooks_that_never_respond() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        // reads the request but doesn't answer it until long after the timeout
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            thread::sleep(Duration::from_millis(500));
+        });
+        let path = dead_letter("stalled");
+        let retry = RetryPolicy {
+            retries: 0,
+            timeout: Duration::from_millis(100),
+            ..retry()
+        };
+        let sink = WebhookSink::new(&url, HashMap::new(), retry, Some(&path)).unwrap();
+        assert!(matches!(
+            sink.send(&event()).await,
+            Err(SstraError::Delivery(_))
+        ));
+        server.join().unwrap();
+        let letter: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(letter["error"].as_str().unwrap().contains("timed out"));
+        fs::remove_file(&path).unwrap();
+    }
+}
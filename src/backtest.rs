@@ -0,0 +1,453 @@
+//! Replays historical bars through simple rule-based strategies.
+//!
+//! Signals are calculated on each bar's close and orders are filled at the
+//! next bar's open, so a strategy never trades on a price it couldn't have
+//! seen yet. Every buy invests as much of the cash as buys whole shares and
+//! every sell closes the whole position. Opens are adjusted for splits and
+//! dividends like the closes, so trades and equity are on one price basis.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::risk::{self, Drawdown};
+use crate::{closing_prices, n_window_rsi, n_window_sma, Bar};
+
+/// When to hold a position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// Hold while the fast simple moving average is above the slow one.
+    SmaCrossover { fast: usize, slow: usize },
+    /// Buy once the RSI falls below `oversold` and sell once it rises above
+    /// `overbought`.
+    RsiThresholds {
+        period: usize,
+        oversold: f64,
+        overbought: f64,
+    },
+}
+
+/// The names strategies are given on the command line.
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sma-crossover" => Ok(Strategy::SmaCrossover { fast: 20, slow: 50 }),
+            "rsi" => Ok(Strategy::RsiThresholds {
+                period: 14,
+                oversold: 30.0,
+                overbought: 70.0,
+            }),
+            _ => Err(format!("Unknown strategy {}", s)),
+        }
+    }
+}
+
+/// How a backtest trades.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BacktestParameters {
+    pub strategy: Strategy,
+    /// The cash the backtest starts with.
+    pub initial_capital: f64,
+    /// The flat fee charged for every order.
+    pub commission: f64,
+    /// How much worse than the open every order is filled, as a fraction of
+    /// the price, e.g. 0.001 for 0.1%.
+    pub slippage: f64,
+    /// The annual risk-free rate for the Sharpe ratio, e.g. 0.04.
+    pub risk_free_rate: f64,
+}
+
+/// A round trip from a buy to the sell that closed it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Trade {
+    pub entry_date: NaiveDate,
+    /// The adjusted price filled at, including slippage.
+    pub entry_price: f64,
+    pub exit_date: NaiveDate,
+    pub exit_price: f64,
+    pub quantity: f64,
+    /// The profit after both orders' commissions.
+    pub profit: f64,
+    /// The profit as a percentage of what the position cost.
+    pub return_percent: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BacktestSummary {
+    pub initial_capital: f64,
+    pub final_equity: f64,
+    /// The change in equity, as a percentage.
+    pub total_return: f64,
+    /// The compound annual growth rate, as a percentage.
+    pub cagr: f64,
+    /// The annualized Sharpe ratio of the daily changes in equity, or `None`
+    /// if the equity never changed.
+    pub sharpe_ratio: Option<f64>,
+    pub max_drawdown: Option<Drawdown>,
+    /// The percentage of trades that made a profit, or `None` without trades.
+    pub win_rate: Option<f64>,
+    pub trades: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BacktestReport {
+    pub symbol: String,
+    pub summary: BacktestSummary,
+    pub trades: Vec<Trade>,
+    /// The cash plus the value of the position at each close.
+    pub equity_curve: Vec<(NaiveDate, f64)>,
+}
+
+/// Runs `parameters.strategy` over `bars`, or returns `None` if there are
+/// fewer than two bars.
+///
+/// A position still open after the last bar is sold at its close.
+pub async fn backtest(
+    symbol: &str,
+    bars: &[Bar],
+    parameters: BacktestParameters,
+) -> Option<BacktestReport> {
+    if bars.len() < 2 {
+        return None;
+    }
+    let signals = signals(parameters.strategy, &closing_prices(bars)).await?;
+
+    let mut cash = parameters.initial_capital;
+    // (entry date, entry price, quantity, what the position cost)
+    let mut position: Option<(NaiveDate, f64, f64, f64)> = None;
+    let mut trades = Vec::new();
+    let mut equity_curve = Vec::new();
+    for (i, bar) in bars.iter().enumerate() {
+        let long = i > 0 && signals[i - 1];
+        let last = i == bars.len() - 1;
+        if long && position.is_none() {
+            let price = adjusted_open(bar) * (1.0 + parameters.slippage);
+            let quantity = ((cash - parameters.commission) / price).floor();
+            if quantity > 0.0 {
+                let cost = quantity * price + parameters.commission;
+                cash -= cost;
+                position = Some((bar.date(), price, quantity, cost));
+            }
+        }
+        // a position is closed at the open when the signal says so, or at
+        // the last close
+        let exit_price = if !long {
+            Some(adjusted_open(bar) * (1.0 - parameters.slippage))
+        } else if last {
+            Some(bar.adjclose * (1.0 - parameters.slippage))
+        } else {
+            None
+        };
+        if let (Some((entry_date, entry_price, quantity, cost)), Some(exit_price)) =
+            (position, exit_price)
+        {
+            let proceeds = quantity * exit_price - parameters.commission;
+            cash += proceeds;
+            position = None;
+            trades.push(Trade {
+                entry_date,
+                entry_price,
+                exit_date: bar.date(),
+                exit_price,
+                quantity,
+                profit: proceeds - cost,
+                return_percent: (proceeds - cost) * 100.0 / cost,
+            });
+        }
+        let holdings = position.map_or(0.0, |(_, _, quantity, _)| quantity * bar.adjclose);
+        equity_curve.push((bar.date(), cash + holdings));
+    }
+
+    let summary = summarize(&parameters, &trades, &equity_curve).await;
+    Some(BacktestReport {
+        symbol: symbol.to_string(),
+        summary,
+        trades,
+        equity_curve,
+    })
+}
+
+/// The bar's open, adjusted by as much as its close is.
+fn adjusted_open(bar: &Bar) -> f64 {
+    if bar.close == 0.0 {
+        bar.open
+    } else {
+        bar.open * bar.adjclose / bar.close
+    }
+}
+
+/// Whether the strategy wants to hold a position after each close.
+async fn signals(strategy: Strategy, prices: &[f64]) -> Option<Vec<bool>> {
+    match strategy {
+        Strategy::SmaCrossover { fast, slow } => {
+            let fast_averages = n_window_sma(fast, prices).await?;
+            let slow_averages = n_window_sma(slow, prices).await?;
+            // both averages end on the last price
+            let fast_offset = prices.len() - fast_averages.len();
+            let slow_offset = prices.len() - slow_averages.len();
+            Some(
+                (0..prices.len())
+                    .map(|i| {
+                        i >= fast_offset
+                            && i >= slow_offset
+                            && fast_averages[i - fast_offset] > slow_averages[i - slow_offset]
+                    })
+                    .collect(),
+            )
+        }
+        Strategy::RsiThresholds {
+            period,
+            oversold,
+            overbought,
+        } => {
+            let indices = n_window_rsi(period, prices).await?;
+            let offset = prices.len() - indices.len();
+            let mut long = false;
+            Some(
+                (0..prices.len())
+                    .map(|i| {
+                        if i >= offset {
+                            let rsi = indices[i - offset];
+                            if rsi < oversold {
+                                long = true;
+                            } else if rsi > overbought {
+                                long = false;
+                            }
+                        }
+                        long
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+async fn summarize(
+    parameters: &BacktestParameters,
+    trades: &[Trade],
+    equity_curve: &[(NaiveDate, f64)],
+) -> BacktestSummary {
+    let initial_capital = parameters.initial_capital;
+    let final_equity = equity_curve.last().map_or(initial_capital, |(_, e)| *e);
+    let days = match (equity_curve.first(), equity_curve.last()) {
+        (Some((first, _)), Some((last, _))) => (*last - *first).num_days(),
+        _ => 0,
+    };
+    let growth = final_equity / initial_capital;
+    let cagr = if days > 0 {
+        (growth.powf(365.25 / days as f64) - 1.0) * 100.0
+    } else {
+        0.0
+    };
+    let equity: Vec<f64> = equity_curve.iter().map(|(_, e)| *e).collect();
+    let returns = risk::simple_returns(&equity).await;
+    let wins = trades.iter().filter(|trade| trade.profit > 0.0).count();
+    BacktestSummary {
+        initial_capital,
+        final_equity,
+        total_return: (growth - 1.0) * 100.0,
+        cagr,
+        sharpe_ratio: risk::sharpe_ratio(&returns, parameters.risk_free_rate).await,
+        max_drawdown: risk::dated_max_drawdown(equity_curve).await,
+        win_rate: if trades.is_empty() {
+            None
+        } else {
+            Some(wins as f64 * 100.0 / trades.len() as f64)
+        },
+        trades: trades.len(),
+    }
+}
+
+impl fmt::Display for BacktestReport {
+    /// Writes the summary, the trades and the equity curve as CSV tables,
+    /// separated by blank lines.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let summary = &self.summary;
+        let optional = |value: Option<f64>, suffix: &str| {
+            value
+                .map(|value| format!("{:.2}{}", value, suffix))
+                .unwrap_or_default()
+        };
+        writeln!(
+            f,
+            "symbol,initial capital,final equity,total return %,cagr %,sharpe,max drawdown %,win rate %,trades"
+        )?;
+        writeln!(
+            f,
+            "{},${:.2},${:.2},{:.2}%,{:.2}%,{},{},{},{}",
+            self.symbol,
+            summary.initial_capital,
+            summary.final_equity,
+            summary.total_return,
+            summary.cagr,
+            optional(summary.sharpe_ratio, ""),
+            optional(summary.max_drawdown.as_ref().map(|d| d.depth * 100.0), "%"),
+            optional(summary.win_rate, "%"),
+            summary.trades,
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "entry date,entry price,exit date,exit price,quantity,profit,return %"
+        )?;
+        for trade in &self.trades {
+            writeln!(
+                f,
+                "{},${:.2},{},${:.2},{},${:.2},{:.2}%",
+                trade.entry_date,
+                trade.entry_price,
+                trade.exit_date,
+                trade.exit_price,
+                trade.quantity,
+                trade.profit,
+                trade.return_percent,
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "date,equity")?;
+        for (date, equity) in &self.equity_curve {
+            writeln!(f, "{},${:.2}", date, equity)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::day_start;
+    use chrono::Duration;
+
+    /// One bar per day from 2020-01-01, opening at the previous close.
+    fn bars(prices: &[f64]) -> Vec<Bar> {
+        let start = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| Bar {
+                timestamp: day_start(start + Duration::days(i as i64)),
+                open: if i > 0 { prices[i - 1] } else { close },
+                close,
+                adjclose: close,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn parameters(strategy: Strategy) -> BacktestParameters {
+        BacktestParameters {
+            strategy,
+            initial_capital: 1000.0,
+            commission: 0.0,
+            slippage: 0.0,
+            risk_free_rate: 0.0,
+        }
+    }
+
+    #[test]
+    fn trades_sma_crossovers() {
+        let strategy = Strategy::SmaCrossover { fast: 1, slow: 2 };
+        let prices = bars(&[10.0, 10.0, 20.0, 25.0, 20.0, 10.0]);
+        let report = tokio_test::block_on(backtest("TEST", &prices, parameters(strategy))).unwrap();
+        // rising on the third close, bought at the fourth open, and falling
+        // on the fifth close, sold at the sixth open
+        assert_eq!(
+            vec![Trade {
+                entry_date: prices[3].date(),
+                entry_price: 20.0,
+                exit_date: prices[5].date(),
+                exit_price: 20.0,
+                quantity: 50.0,
+                profit: 0.0,
+                return_percent: 0.0,
+            }],
+            report.trades
+        );
+        assert_eq!(
+            vec![1000.0, 1000.0, 1000.0, 1250.0, 1000.0, 1000.0],
+            report
+                .equity_curve
+                .iter()
+                .map(|(_, e)| *e)
+                .collect::<Vec<f64>>()
+        );
+        assert_eq!(Some(0.0), report.summary.win_rate);
+        assert!((report.summary.max_drawdown.unwrap().depth - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn charges_commission_and_slippage() {
+        let strategy = Strategy::SmaCrossover { fast: 1, slow: 2 };
+        let prices = bars(&[10.0, 10.0, 20.0, 25.0, 30.0]);
+        let report = tokio_test::block_on(backtest(
+            "TEST",
+            &prices,
+            BacktestParameters {
+                commission: 1.0,
+                slippage: 0.5,
+                ..parameters(strategy)
+            },
+        ))
+        .unwrap();
+        // bought at the open of 20 plus half and sold at the last close of 30,
+        // less half
+        let trade = &report.trades[0];
+        assert_eq!(
+            (30.0, 15.0, 33.0),
+            (trade.entry_price, trade.exit_price, trade.quantity)
+        );
+        assert_eq!(33.0 * 15.0 - 1.0 - (33.0 * 30.0 + 1.0), trade.profit);
+        assert_eq!(1000.0 + trade.profit, report.summary.final_equity);
+        assert_eq!(Some(0.0), report.summary.win_rate);
+    }
+
+    #[test]
+    fn fills_at_adjusted_opens_across_splits() {
+        let strategy = Strategy::SmaCrossover { fast: 1, slow: 2 };
+        let mut prices = bars(&[10.0, 10.0, 20.0, 25.0, 30.0]);
+        // a 2:1 split before the last bar doubles every raw price before it
+        for bar in &mut prices[..4] {
+            bar.open *= 2.0;
+            bar.close *= 2.0;
+        }
+        let report = tokio_test::block_on(backtest("TEST", &prices, parameters(strategy))).unwrap();
+        let trade = &report.trades[0];
+        assert_eq!(
+            (20.0, 30.0, 50.0),
+            (trade.entry_price, trade.exit_price, trade.quantity)
+        );
+        assert_eq!(
+            vec![1000.0, 1000.0, 1000.0, 1250.0, 1500.0],
+            report
+                .equity_curve
+                .iter()
+                .map(|(_, e)| *e)
+                .collect::<Vec<f64>>()
+        );
+    }
+
+    #[test]
+    fn trades_rsi_thresholds() {
+        let strategy = Strategy::RsiThresholds {
+            period: 1,
+            oversold: 30.0,
+            overbought: 70.0,
+        };
+        // an RSI over one day is 0 after a fall and 100 after a rise
+        let prices = bars(&[10.0, 8.0, 9.0, 9.0, 12.0, 11.0]);
+        let report = tokio_test::block_on(backtest("TEST", &prices, parameters(strategy))).unwrap();
+        assert_eq!(1, report.trades.len());
+        let trade = &report.trades[0];
+        assert_eq!(
+            (prices[2].date(), 8.0),
+            (trade.entry_date, trade.entry_price)
+        );
+        assert_eq!((prices[3].date(), 9.0), (trade.exit_date, trade.exit_price));
+        assert_eq!(Some(100.0), report.summary.win_rate);
+        assert_eq!(1125.0, report.summary.final_equity);
+    }
+}
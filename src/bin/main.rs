@@ -1,68 +1,602 @@
-use std::{process, thread, time};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::{process, time};
 
-use chrono::Utc;
-use clap::{load_yaml, App};
+use chrono::{NaiveTime, Utc};
+use clap::{load_yaml, value_t, App, ArgMatches};
+use futures::StreamExt;
 
 use sstra::*;
 
-static MOV_AVG_NUM_DAYS: i32 = 30;
+fn main() {
+    // The Yahoo! Finance and webhook clients and the polling timers run on
+    // tokio 1.x while actix drives its own tokio 0.2 runtime, so keep a 1.x
+    // runtime entered alongside the system.
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    actix_rt::System::new("sstra").block_on(run());
+}
 
-#[actix_rt::main]
-async fn main() {
+async fn run() {
     let yaml = load_yaml!("cli.yaml");
     let matches = App::from(yaml).get_matches();
-    let now = Utc::now().format("%Y-%m-%d").to_string();
-
-    let from_in: &str = matches.value_of("from").unwrap();
-    let from_split: Vec<&str> = from_in.split('T').collect();
-    let from = from_split[0];
-    let symbols: Vec<&str> = matches.values_of("symbols").unwrap().collect();
+    if let Some(matches) = matches.subcommand_matches("backtest") {
+        run_backtest(matches).await;
+        return;
+    }
+    let exchange = value_t!(matches, "exchange", Exchange).unwrap_or_else(|e| e.exit());
+    let bar_interval = value_t!(matches, "bar-interval", Interval).unwrap_or_else(|e| e.exit());
+    // the period ends on the exchange's date, which may not be UTC's
+    let now = exchange.today().to_string();
+    let to = match matches.value_of("to") {
+        Some(to_in) => to_in.split('T').next().unwrap().to_string(),
+        None => now.clone(),
+    };
+    let trading_days = matches
+        .value_of("trading-days")
+        .map(|_| value_t!(matches, "trading-days", usize).unwrap_or_else(|e| e.exit()));
+    let from = match (matches.value_of("from"), trading_days) {
+        (Some(from_in), _) => from_in.split('T').next().unwrap().to_string(),
+        (None, Some(days)) => match parse_date(&to) {
+            Ok(period_end) => exchange.trading_days_before(period_end, days).to_string(),
+            Err(_) => to.clone(),
+        },
+        (None, None) => unreachable!("clap requires --from or --trading-days"),
+    };
+    let concurrency = value_t!(matches, "concurrency", usize).unwrap_or_else(|e| e.exit());
+    let ordered = matches.is_present("ordered");
+    let format = value_t!(matches, "format", OutputFormat).unwrap_or_else(|e| e.exit());
+    let benchmark = matches.value_of("benchmark").map(str::to_uppercase);
+    let risk_parameters = RiskParameters {
+        risk_free_rate: value_t!(matches, "risk-free-rate", f64).unwrap_or_else(|e| e.exit())
+            / 100.0,
+    };
+    let indicators = Indicators {
+        sma_windows: windows(&matches, "sma"),
+        ema_windows: windows(&matches, "ema"),
+        ema_smoothing: value_t!(matches, "ema-smoothing", f64).unwrap_or_else(|e| e.exit()),
+        wma_windows: windows(&matches, "wma"),
+        rsi_period: windows(&matches, "rsi").first().copied(),
+        macd: if matches.is_present("macd") {
+            match windows(&matches, "macd-periods")[..] {
+                [fast, slow, signal] => Some(MacdPeriods { fast, slow, signal }),
+                _ => unreachable!("clap requires three MACD periods"),
+            }
+        } else {
+            None
+        },
+        bollinger: if matches.is_present("bollinger") {
+            Some(BollingerParameters {
+                window: windows(&matches, "bollinger-window")[0],
+                width: value_t!(matches, "bollinger-width", f64).unwrap_or_else(|e| e.exit()),
+            })
+        } else {
+            None
+        },
+        risk: Some(risk_parameters).filter(|_| matches.is_present("risk")),
+        benchmark: Some(risk_parameters).filter(|_| matches.is_present("benchmark")),
+    };
+    let mut alerts = alert_engine(&matches);
+    // the portfolio and ledger reports only need the latest prices
+    let mut indicators = if matches.is_present("holdings") || matches.is_present("ledger") {
+        Indicators {
+            sma_windows: Vec::new(),
+            ..Default::default()
+        }
+    } else {
+        indicators
+    };
+    if let Some(alerts) = &alerts {
+        alerts.require(&mut indicators);
+    }
+    let matrix = matches.is_present("matrix");
+    // risk statistics and correlations are calculated on daily returns
+    if !bar_interval.is_day() {
+        for name in &["risk", "benchmark", "matrix"] {
+            if matches.is_present(name) {
+                eprintln!("--{} needs daily bars, not {} ones.", name, bar_interval);
+                process::exit(1);
+            }
+        }
+    }
+    let once = matches.is_present("once");
+    let interval = matches
+        .value_of("interval")
+        .map(|interval| {
+            parse_interval(interval).unwrap_or_else(|| {
+                eprintln!(
+                    "Invalid interval {}, please enter e.g. 30s, 5m or 1h.",
+                    interval
+                );
+                process::exit(1);
+            })
+        })
+        .unwrap();
+    let session = market_session(&matches, exchange);
+    // a correlation needs at least two returns, i.e. three prices
+    let longest_window = if matrix {
+        3
+    } else {
+        indicators.longest_window()
+    };
 
     if matches.is_present("debug") {
-        eprintln!("Calculating the period from {} until {}...", from, now);
+        eprintln!("Calculating the period from {} until {}...", from, to);
     }
-    let period = count_days(&from, &now).unwrap_or_else(|err| {
+    let period = count_days(&from, &to).unwrap_or_else(|err| {
         eprintln!("{}, please enter a date in the form YYYY-MM-DD.", err);
         process::exit(1);
     });
+    // both dates parsed successfully in count_days
+    let period_start = parse_date(&from).unwrap();
+    let period_end = parse_date(&to).unwrap();
 
-    // this is what we need to do to cast a string to an integer
-    let days: i32 = period.split("d").collect::<Vec<&str>>()[0]
-        .parse()
-        .unwrap_or(0);
+    if let Err(err) = bar_interval.check_period(period_start, period_end, exchange.today()) {
+        eprintln!("{}.", err);
+        process::exit(1);
+    }
+    // every window needs a bar for each of its days, and there's only one a
+    // trading day unless the bars are intraday ones
+    let days = exchange.trading_days(period_start, period_end);
+    let bars = days * exchange.session().bars_per_day(bar_interval);
+    if bars < longest_window {
+        if bar_interval.is_day() {
+            eprintln!(
+                "Please select a period of at least {} trading days, it has {}.",
+                longest_window, days
+            );
+        } else {
+            eprintln!(
+                "Please select a period of at least {} {} bars, it has about {}.",
+                longest_window, bar_interval, bars
+            );
+        }
+        process::exit(1);
+    }
 
-    if days < MOV_AVG_NUM_DAYS {
+    if matches.is_present("debug") {
         eprintln!(
-            "Please select a start date more than {} days in the past.",
-            MOV_AVG_NUM_DAYS
+            "Gathering info for a period of {} ({} trading days) for:",
+            period, days
         );
+    }
+    let holdings = matches.value_of("holdings").map(|path| {
+        portfolio::read_holdings(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        })
+    });
+    let ledger = matches.value_of("ledger").map(|path| {
+        let method = value_t!(matches, "lots", LotMethod).unwrap_or_else(|e| e.exit());
+        portfolio::ledger::read_ledger(path)
+            .and_then(|transactions| portfolio::ledger::replay(&transactions, method, period_end))
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            })
+    });
+    let symbols: Vec<String> = match (&holdings, &ledger) {
+        (Some(holdings), _) => {
+            let mut symbols: Vec<String> = Vec::new();
+            for holding in holdings {
+                let symbol = holding.symbol.to_uppercase();
+                if !symbols.contains(&symbol) {
+                    symbols.push(symbol);
+                }
+            }
+            symbols
+        }
+        (None, Some(ledger)) => ledger.symbols(),
+        (None, None) => matches
+            .values_of("symbols")
+            .unwrap()
+            .map(str::to_uppercase)
+            .collect(),
+    };
+    if let Some(alerts) = &alerts {
+        for symbol in alerts.symbols() {
+            if !symbols.contains(&symbol) {
+                eprintln!(
+                    "Alerts for {} won't fire, it isn't one of the symbols.",
+                    symbol
+                );
+            }
+        }
+    }
+    // the header is printed before the first poll, which may never come if
+    // the market stays closed
+    let mut header = format == OutputFormat::Csv && !matches.is_present("no-headers");
+    let provider = price_provider(&matches);
+    let fetcher = actix::Supervisor::start(move |_| StockPriceFetcher::new(provider));
+    let processor = actix::Supervisor::start(|_| StockPriceProcessor);
+    loop {
+        if let Some(session) = &session {
+            let now = Utc::now();
+            let open = session.next_open(now);
+            if open > now {
+                if once {
+                    eprintln!("The market is closed, not polling.");
+                    return;
+                }
+                if matches.is_present("debug") {
+                    eprintln!("The market is closed, waiting until {}.", open);
+                }
+                tokio::time::sleep((open - now).to_std().unwrap()).await;
+            }
+        }
+        if header {
+            if holdings.is_some() {
+                println!("{}", PortfolioReport::csv_header());
+            } else if ledger.is_some() {
+                println!("{}", LedgerReport::csv_header());
+            } else if !matrix {
+                println!("{}", StockInfo::csv_header(&indicators, bar_interval));
+            }
+            header = false;
+        }
+        // without a --to date the period runs until the day of each poll
+        let period_end = match matches.value_of("to") {
+            Some(_) => period_end,
+            None => exchange.today(),
+        };
+        let period_start = match trading_days {
+            Some(days) => exchange.trading_days_before(period_end, days),
+            None => period_start,
+        };
+        let queries = symbols
+            .iter()
+            .map(|stock| StockQuery {
+                interval: bar_interval,
+                ..StockQuery::new(stock.clone(), period_start, period_end, indicators.clone())
+            })
+            .collect();
+        if matrix {
+            let covariance = matches.is_present("covariance");
+            report_matrix(fetcher.clone(), queries, concurrency, format, covariance).await;
+        } else if let Some(holdings) = &holdings {
+            report_portfolio(
+                fetcher.clone(),
+                processor.clone(),
+                queries,
+                holdings,
+                concurrency,
+                format,
+            )
+            .await;
+        } else if let Some(ledger) = &ledger {
+            let infos =
+                collect_infos(fetcher.clone(), processor.clone(), queries, concurrency).await;
+            let report = ledger.clone().report(&infos);
+            match format {
+                OutputFormat::Csv => print!("{}", report),
+                OutputFormat::Json | OutputFormat::Ndjson => {
+                    println!("{}", serde_json::to_string(&report).unwrap())
+                }
+            }
+        } else {
+            let benchmark_query = benchmark.as_ref().map(|symbol| StockQuery {
+                interval: bar_interval,
+                ..StockQuery::new(symbol.clone(), period_start, period_end, indicators.clone())
+            });
+            let mut results = process_all(
+                fetcher.clone(),
+                processor.clone(),
+                queries,
+                benchmark_query,
+                concurrency,
+                ordered,
+            );
+            let mut collected = Vec::new();
+            while let Some((_, result)) = results.next().await {
+                match result {
+                    Ok(info) => {
+                        if let Some(alerts) = alerts.as_mut() {
+                            for err in alerts.process(&info).await {
+                                eprintln!("{}", err);
+                            }
+                        }
+                        match format {
+                            // the benchmark couldn't be fetched or compared
+                            // with the symbol, so its columns stay empty
+                            OutputFormat::Csv
+                                if benchmark.is_some() && info.benchmark.is_none() =>
+                            {
+                                println!("{},,,,,,", info)
+                            }
+                            OutputFormat::Csv => println!("{}", info),
+                            OutputFormat::Ndjson => {
+                                println!("{}", serde_json::to_string(&info).unwrap())
+                            }
+                            OutputFormat::Json => collected.push(info),
+                        }
+                    }
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+            if format == OutputFormat::Json {
+                println!("{}", serde_json::to_string(&collected).unwrap());
+            }
+        }
+        if once {
+            return;
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Prints the report on every holding whose prices could be fetched.
+async fn report_portfolio(
+    fetcher: actix::Addr<StockPriceFetcher>,
+    processor: actix::Addr<StockPriceProcessor>,
+    queries: Vec<StockQuery>,
+    holdings: &[Holding],
+    concurrency: usize,
+    format: OutputFormat,
+) {
+    let infos = collect_infos(fetcher, processor, queries, concurrency).await;
+    let report = match portfolio::aggregate(holdings, &infos) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    match format {
+        OutputFormat::Csv => print!("{}", report),
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            println!("{}", serde_json::to_string(&report).unwrap())
+        }
+    }
+}
+
+/// Runs the `backtest` subcommand once for every symbol.
+async fn run_backtest(matches: &ArgMatches<'_>) {
+    let exchange = value_t!(matches, "exchange", Exchange).unwrap_or_else(|e| e.exit());
+    let date = |name: &str| {
+        let value = matches
+            .value_of(name)
+            .map(|value| value.split('T').next().unwrap().to_string())
+            .unwrap_or_else(|| exchange.today().to_string());
+        parse_date(&value).unwrap_or_else(|err| {
+            eprintln!("{}, please enter a date in the form YYYY-MM-DD.", err);
+            process::exit(1);
+        })
+    };
+    let (period_start, period_end) = (date("from"), date("to"));
+    let number = |name: &str| value_t!(matches, name, f64).unwrap_or_else(|e| e.exit());
+    let strategy =
+        match value_t!(matches, "strategy", backtest::Strategy).unwrap_or_else(|e| e.exit()) {
+            backtest::Strategy::SmaCrossover { .. } => backtest::Strategy::SmaCrossover {
+                fast: windows(matches, "fast")[0],
+                slow: windows(matches, "slow")[0],
+            },
+            backtest::Strategy::RsiThresholds { .. } => backtest::Strategy::RsiThresholds {
+                period: windows(matches, "rsi-period")[0],
+                oversold: number("oversold"),
+                overbought: number("overbought"),
+            },
+        };
+    let parameters = backtest::BacktestParameters {
+        strategy,
+        initial_capital: number("capital"),
+        commission: number("commission"),
+        slippage: number("slippage") / 100.0,
+        risk_free_rate: number("risk-free-rate") / 100.0,
+    };
+    let concurrency = value_t!(matches, "concurrency", usize).unwrap_or_else(|e| e.exit());
+    let format = value_t!(matches, "format", OutputFormat).unwrap_or_else(|e| e.exit());
+
+    let provider = price_provider(matches);
+    let fetcher = actix::Supervisor::start(move |_| StockPriceFetcher::new(provider));
+    let queries = matches
+        .values_of("symbols")
+        .unwrap()
+        .map(|symbol| {
+            StockQuery::new(
+                symbol.to_uppercase(),
+                period_start,
+                period_end,
+                Indicators::default(),
+            )
+        })
+        .collect();
+    let mut reports = Vec::new();
+    let mut results = fetch_all(fetcher, queries, concurrency);
+    while let Some((symbol, result)) = results.next().await {
+        let prices = match result {
+            Ok(prices) => prices,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        match backtest::backtest(&symbol, &prices.bars, parameters).await {
+            Some(report) => match format {
+                OutputFormat::Csv => println!("{}", report),
+                OutputFormat::Ndjson => println!("{}", serde_json::to_string(&report).unwrap()),
+                OutputFormat::Json => reports.push(report),
+            },
+            None => eprintln!("Not enough prices to backtest {}.", symbol),
+        }
+    }
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string(&reports).unwrap());
+    }
+}
+
+/// The alerts selected by the `--alert`, `--alert-rules`, `--alert-log` and
+/// `--webhook` options, if there are any rules.
+fn alert_engine(matches: &ArgMatches) -> Option<AlertEngine> {
+    let mut rules = match matches.value_of("alert-rules") {
+        Some(path) => alert::read_rules(path),
+        None => Ok(Vec::new()),
+    }
+    .and_then(|mut rules| {
+        for rule in matches.values_of("alert").into_iter().flatten() {
+            rules.push(rule.parse()?);
+        }
+        Ok(rules)
+    })
+    .unwrap_or_else(|err| {
+        eprintln!("{}", err);
         process::exit(1);
+    });
+    if rules.is_empty() {
+        return None;
+    }
+    rules.dedup();
+    let mut sinks: Vec<Box<dyn AlertSink>> = vec![match matches.value_of("alert-log") {
+        Some(path) => Box::new(FileSink::new(path)),
+        None => Box::new(StderrSink),
+    }];
+    if let Some(url) = matches.value_of("webhook") {
+        let templates = match matches.value_of("webhook-template") {
+            Some(path) => alert::webhook::read_templates(path).unwrap_or_else(|err| {
+                eprintln!("Invalid webhook templates {}: {}", path, err);
+                process::exit(1);
+            }),
+            None => HashMap::new(),
+        };
+        let retry = RetryPolicy {
+            retries: value_t!(matches, "webhook-retries", u32).unwrap_or_else(|e| e.exit()),
+            backoff: time::Duration::from_millis(
+                value_t!(matches, "webhook-backoff", u64).unwrap_or_else(|e| e.exit()),
+            ),
+            timeout: time::Duration::from_secs(
+                value_t!(matches, "webhook-timeout", u64).unwrap_or_else(|e| e.exit()),
+            ),
+        };
+        let dead_letter = matches.value_of("webhook-dead-letter");
+        let sink = WebhookSink::new(url, templates, retry, dead_letter).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        sinks.push(Box::new(sink));
     }
+    Some(AlertEngine::new(rules, sinks))
+}
 
-    if matches.is_present("debug") {
-        eprintln!("Gathering info from the past {} for:", period);
+/// The provider selected by the `--data` and `--cache` options.
+fn price_provider(matches: &ArgMatches) -> Rc<dyn PriceProvider> {
+    let source: Box<dyn PriceProvider> = match matches.value_of("data") {
+        Some(path) => Box::new(FileProvider::new(path)),
+        None => Box::new(YahooProvider::new()),
+    };
+    match matches.value_of("cache") {
+        Some(directory) => Rc::new(CachedProvider::new(directory, source)),
+        None => Rc::from(source),
     }
-    if !matches.is_present("no-headers") {
-        println!(
-            "period start,symbol,price,change %,min,max,{}d avg",
-            MOV_AVG_NUM_DAYS
-        );
+}
+
+/// Processes every query, printing the errors and returning the rest.
+async fn collect_infos(
+    fetcher: actix::Addr<StockPriceFetcher>,
+    processor: actix::Addr<StockPriceProcessor>,
+    queries: Vec<StockQuery>,
+    concurrency: usize,
+) -> Vec<StockInfo> {
+    let mut infos = Vec::new();
+    let mut results = process_all(fetcher, processor, queries, None, concurrency, true);
+    while let Some((_, result)) = results.next().await {
+        match result {
+            Ok(info) => infos.push(info),
+            Err(err) => eprintln!("{}", err),
+        }
     }
+    infos
+}
 
-    let fetcher = actix::Supervisor::start(|_| StockPriceFetcher);
-    let processor = actix::Supervisor::start(|_| StockPriceProcessor);
-    loop {
-        for stock in &symbols {
-            let symbol = stock.to_uppercase();
-            let stock_prices = fetcher
-                .send(StockQuery::new(symbol, from.to_string(), MOV_AVG_NUM_DAYS).await)
-                .await;
-            let result = processor.send(stock_prices).await;
-            match result {
-                Ok(res) => println!("{}", res.unwrap()),
-                Err(err) => eprintln!("{}", err),
+/// Prints the correlation matrix of every symbol that could be fetched.
+async fn report_matrix(
+    fetcher: actix::Addr<StockPriceFetcher>,
+    queries: Vec<StockQuery>,
+    concurrency: usize,
+    format: OutputFormat,
+    covariance: bool,
+) {
+    let mut series = Vec::new();
+    let mut results = fetch_all(fetcher, queries, concurrency);
+    while let Some((symbol, result)) = results.next().await {
+        match result {
+            Ok(prices) => series.push((symbol, prices.bars)),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    match risk::correlation_matrix(&series, covariance).await {
+        Some(matrix) => match format {
+            OutputFormat::Csv => print!("{}", matrix),
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                println!("{}", serde_json::to_string(&matrix).unwrap())
+            }
+        },
+        None => eprintln!("Fewer than 3 days with prices for every symbol."),
+    }
+}
+
+/// The exchange's trading session, changed by the `--session` and
+/// `--holidays` options, if `--market-hours` limits polling to it.
+fn market_session(matches: &ArgMatches, exchange: Exchange) -> Option<Session> {
+    if !matches.is_present("market-hours") {
+        return None;
+    }
+    let mut session = exchange.session();
+    if let Some(hours) = matches.value_of("session") {
+        let times: Vec<_> = hours
+            .split('-')
+            .map(|time| NaiveTime::parse_from_str(time.trim(), "%H:%M"))
+            .collect();
+        match times[..] {
+            [Ok(open), Ok(close)] if open < close => {
+                session.open = open;
+                session.close = close;
+            }
+            _ => {
+                eprintln!("Invalid session {}, please enter e.g. 09:30-16:00.", hours);
+                process::exit(1);
             }
         }
-        thread::sleep(time::Duration::from_secs(30));
     }
+    if let Some(path) = matches.value_of("holidays") {
+        session.holidays = exchange::read_holidays(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+    }
+    Some(session)
+}
+
+/// Parses a polling interval such as 30s, 5m or 1h, in seconds by default.
+fn parse_interval(interval: &str) -> Option<time::Duration> {
+    let (number, unit) = match interval.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => interval.split_at(index),
+        None => (interval, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return None,
+    };
+    match number.parse::<u64>() {
+        Ok(number) if number > 0 => Some(time::Duration::from_secs(number * seconds)),
+        _ => None,
+    }
+}
+
+/// Parses a list of indicator windows, in days.
+fn windows(matches: &ArgMatches, name: &str) -> Vec<usize> {
+    matches
+        .values_of(name)
+        .map(|values| {
+            values
+                .map(|window| match window.parse() {
+                    Ok(days) if days > 0 => days,
+                    _ => {
+                        eprintln!("Invalid {} window {}.", name, window);
+                        process::exit(1);
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default()
 }
@@ -0,0 +1,38 @@
+//! The simple comma-separated files prices, holdings and ledgers are read
+//! from.
+
+/// Parses a simple comma-separated file; quoted fields are not supported.
+///
+/// The first line names the columns, which are matched without case, spaces
+/// or underscores, e.g. "Adj Close" as `adjclose`. `record` turns each
+/// following row into a value, given the row's number (from 1) and its
+/// trimmed values paired with their columns.
+pub(crate) fn parse<T, F>(contents: &str, mut record: F) -> Result<Vec<T>, String>
+where
+    F: FnMut(usize, &[(&str, &str)]) -> Result<T, String>,
+{
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = match lines.next() {
+        Some(line) => line
+            .split(',')
+            .map(|column| column.trim().to_lowercase().replace(['_', ' '], ""))
+            .collect(),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut records = Vec::new();
+    for (row, line) in lines.enumerate() {
+        let fields: Vec<(&str, &str)> = header
+            .iter()
+            .map(String::as_str)
+            .zip(line.split(',').map(str::trim))
+            .collect();
+        records.push(record(row + 1, &fields)?);
+    }
+    Ok(records)
+}
+
+/// The error for a value that can't be read as its column's type.
+pub(crate) fn invalid(row: usize, column: &str, value: &str) -> String {
+    format!("row {}: invalid {} {:?}", row, column, value)
+}
@@ -0,0 +1,94 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use actix::MailboxError;
+
+/// Everything that can go wrong while fetching or processing a symbol's
+/// prices.
+#[derive(Debug)]
+pub enum SstraError {
+    /// The price provider could not return any data.
+    Provider(String),
+    /// The price provider doesn't know the symbol.
+    UnknownSymbol(String),
+    /// The price provider returned no prices for the symbol.
+    EmptySeries(String),
+    /// A date couldn't be parsed.
+    InvalidDate(String),
+    /// A ledger's transactions don't add up, e.g. selling shares that were
+    /// never bought.
+    InvalidTransaction(String),
+    /// An alert rule couldn't be parsed.
+    InvalidRule(String),
+    /// An alert couldn't be delivered to its sink.
+    Delivery(String),
+    /// Amounts in different currencies would have to be combined, which
+    /// they can't since there are no exchange rates.
+    CurrencyMismatch(String),
+    /// Bars of the requested interval aren't available for the period.
+    UnsupportedPeriod(String),
+    /// There are fewer prices than a calculation's window needs.
+    InsufficientData {
+        symbol: String,
+        needed: usize,
+        available: usize,
+    },
+    /// Reading or writing local files failed.
+    Io(io::Error),
+    /// An actor stopped before answering a message.
+    Mailbox(MailboxError),
+}
+
+impl fmt::Display for SstraError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SstraError::Provider(message) => write!(f, "{}", message),
+            SstraError::UnknownSymbol(symbol) => write!(f, "Unknown symbol {}", symbol),
+            SstraError::EmptySeries(symbol) => write!(f, "No prices available for {}", symbol),
+            SstraError::InvalidDate(message) => write!(f, "Invalid date: {}", message),
+            SstraError::Delivery(message) => write!(f, "{}", message),
+            SstraError::UnsupportedPeriod(message) => {
+                write!(f, "Unsupported period: {}", message)
+            }
+            SstraError::InvalidRule(message) => write!(f, "Invalid alert rule: {}", message),
+            SstraError::CurrencyMismatch(message) => write!(f, "Currency mismatch: {}", message),
+            SstraError::InvalidTransaction(message) => {
+                write!(f, "Invalid transaction: {}", message)
+            }
+            SstraError::InsufficientData {
+                symbol,
+                needed,
+                available,
+            } => write!(
+                f,
+                "Only {} prices available for {}, but {} are needed",
+                available, symbol, needed
+            ),
+            SstraError::Io(err) => write!(f, "{}", err),
+            SstraError::Mailbox(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for SstraError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SstraError::Io(err) => Some(err),
+            SstraError::Mailbox(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SstraError {
+    fn from(err: io::Error) -> Self {
+        SstraError::Io(err)
+    }
+}
+
+impl From<MailboxError> for SstraError {
+    fn from(err: MailboxError) -> Self {
+        SstraError::Mailbox(err)
+    }
+}
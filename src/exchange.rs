@@ -0,0 +1,357 @@
+//! Exchanges' local time, trading sessions and calendars.
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+
+use crate::{parse_date, Interval, SstraError};
+
+mod holidays;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exchange {
+    Nyse,
+    Nasdaq,
+    Lse,
+    Xetra,
+    Tse,
+}
+
+/// The hours an exchange trades, in its local time, and the weekdays it
+/// doesn't.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub exchange: Exchange,
+    pub timezone: Tz,
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    /// Closures besides the exchange's own holidays.
+    pub holidays: Vec<NaiveDate>,
+}
+
+impl FromStr for Exchange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nyse" => Ok(Exchange::Nyse),
+            "nasdaq" => Ok(Exchange::Nasdaq),
+            "lse" => Ok(Exchange::Lse),
+            "xetra" => Ok(Exchange::Xetra),
+            "tse" => Ok(Exchange::Tse),
+            _ => Err(format!("Unknown exchange {}", s)),
+        }
+    }
+}
+
+impl Exchange {
+    pub fn timezone(&self) -> Tz {
+        match self {
+            Exchange::Nyse | Exchange::Nasdaq => Tz::America__New_York,
+            Exchange::Lse => Tz::Europe__London,
+            Exchange::Xetra => Tz::Europe__Berlin,
+            Exchange::Tse => Tz::Asia__Tokyo,
+        }
+    }
+
+    /// The date where the exchange is.
+    pub fn today(&self) -> NaiveDate {
+        today(self.timezone())
+    }
+
+    /// The exchange's regular trading hours. The Tokyo Stock Exchange's
+    /// session runs from its morning open to its afternoon close, over the
+    /// lunch break.
+    pub fn session(&self) -> Session {
+        let (open, close) = match self {
+            Exchange::Nyse | Exchange::Nasdaq => ((9, 30), (16, 0)),
+            Exchange::Lse => ((8, 0), (16, 30)),
+            Exchange::Xetra => ((9, 0), (17, 30)),
+            Exchange::Tse => ((9, 0), (15, 30)),
+        };
+        Session {
+            exchange: *self,
+            timezone: self.timezone(),
+            open: NaiveTime::from_hms_opt(open.0, open.1, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(close.0, close.1, 0).unwrap(),
+            holidays: Vec::new(),
+        }
+    }
+
+    /// Every weekday the exchange is closed on in a year.
+    pub fn holidays(&self, year: i32) -> Vec<NaiveDate> {
+        holidays::holidays(*self, year)
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+            && !self.holidays(date.year()).contains(&date)
+    }
+
+    /// The number of days the exchange trades on from `start` until `end`,
+    /// inclusive.
+    pub fn trading_days(&self, start: NaiveDate, end: NaiveDate) -> usize {
+        let holidays: Vec<NaiveDate> = (start.year()..=end.year())
+            .flat_map(|year| self.holidays(year))
+            .collect();
+        start
+            .iter_days()
+            .take_while(|date| *date <= end)
+            .filter(|date| !matches!(date.weekday(), Weekday::Sat | Weekday::Sun))
+            .filter(|date| !holidays.contains(date))
+            .count()
+    }
+
+    /// The first of the last `days` days the exchange traded on up to
+    /// `end`, inclusive.
+    pub fn trading_days_before(&self, end: NaiveDate, days: usize) -> NaiveDate {
+        let mut start = end;
+        let mut counted = 0;
+        loop {
+            if self.is_trading_day(start) {
+                counted += 1;
+                if counted >= days {
+                    return start;
+                }
+            }
+            start = start.pred_opt().unwrap();
+        }
+    }
+}
+
+/// The `n`th `weekday` of a month, counting from 1.
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u32) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n as u8).unwrap()
+}
+
+/// The last `weekday` of a month.
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    let next_month = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)
+    }
+    .unwrap();
+    let last = next_month.pred_opt().unwrap();
+    let days_back =
+        (7 + last.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+    last - Duration::days(days_back as i64)
+}
+
+/// The date in a timezone.
+pub fn today(timezone: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&timezone).date_naive()
+}
+
+/// The instant a local time happens in a timezone. Local times skipped or
+/// repeated by a daylight saving change are taken to be in standard time.
+pub fn to_utc(timezone: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match timezone.from_local_datetime(&local) {
+        LocalResult::Single(instant) => instant.with_timezone(&Utc),
+        // the clocks went back, and the later time is the standard one
+        LocalResult::Ambiguous(_, standard) => standard.with_timezone(&Utc),
+        // the clocks went forward, so standard time held the day before
+        LocalResult::None => {
+            let offset = timezone
+                .offset_from_utc_datetime(&(local - Duration::days(1)))
+                .fix();
+            Utc.from_utc_datetime(&(local - offset))
+        }
+    }
+}
+
+impl Session {
+    /// Whether the exchange trades at all on a local date.
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        self.exchange.is_trading_day(date) && !self.holidays.contains(&date)
+    }
+
+    /// How many bars of an interval a trading day has, counting a bar cut
+    /// short by the close.
+    pub fn bars_per_day(&self, interval: Interval) -> usize {
+        match interval.minutes() {
+            Some(minutes) => {
+                let session = (self.close - self.open).num_minutes();
+                ((session + minutes - 1) / minutes) as usize
+            }
+            None => 1,
+        }
+    }
+
+    /// Whether the exchange is trading at an instant.
+    pub fn is_open(&self, instant: DateTime<Utc>) -> bool {
+        let local = instant.with_timezone(&self.timezone);
+        self.is_trading_day(local.date_naive())
+            && self.open <= local.time()
+            && local.time() < self.close
+    }
+
+    /// The next time the exchange opens after an instant, or the instant
+    /// itself while the exchange is open.
+    pub fn next_open(&self, instant: DateTime<Utc>) -> DateTime<Utc> {
+        if self.is_open(instant) {
+            return instant;
+        }
+        let mut date = instant.with_timezone(&self.timezone).date_naive();
+        loop {
+            if self.is_trading_day(date) {
+                let open = to_utc(self.timezone, date.and_time(self.open));
+                if open > instant {
+                    return open;
+                }
+            }
+            date = date.succ_opt().unwrap();
+        }
+    }
+}
+
+/// Reads one date per line, in the form YYYY-MM-DD, skipping empty lines and
+/// those starting with #.
+pub fn read_holidays<P: Into<PathBuf>>(path: P) -> Result<Vec<NaiveDate>, SstraError> {
+    fs::read_to_string(path.into())?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_date)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::date;
+
+    fn offset(timezone: Tz, instant: DateTime<Utc>) -> i32 {
+        timezone
+            .offset_from_utc_datetime(&instant.naive_utc())
+            .fix()
+            .local_minus_utc()
+    }
+
+    fn utc(day: &str, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&date(day).and_hms_opt(hour, minute, 0).unwrap())
+    }
+
+    #[test]
+    fn finds_weekdays_of_months() {
+        assert_eq!(date("2024-03-10"), nth_weekday(2024, 3, Weekday::Sun, 2));
+        assert_eq!(date("2024-03-31"), last_weekday(2024, 3, Weekday::Sun));
+        assert_eq!(date("2024-10-27"), last_weekday(2024, 10, Weekday::Sun));
+        assert_eq!(date("2024-12-27"), last_weekday(2024, 12, Weekday::Fri));
+    }
+
+    #[test]
+    fn applies_daylight_saving_time() {
+        let new_york = Exchange::Nyse.timezone();
+        assert_eq!(-5 * 3600, offset(new_york, utc("2024-03-10", 6, 59)));
+        assert_eq!(-4 * 3600, offset(new_york, utc("2024-03-10", 7, 0)));
+        assert_eq!(-4 * 3600, offset(new_york, utc("2024-11-03", 5, 59)));
+        assert_eq!(-5 * 3600, offset(new_york, utc("2024-11-03", 6, 0)));
+
+        let berlin = Exchange::Xetra.timezone();
+        assert_eq!(3600, offset(berlin, utc("2024-03-31", 0, 59)));
+        assert_eq!(7200, offset(berlin, utc("2024-03-31", 1, 0)));
+        assert_eq!(3600, offset(berlin, utc("2024-10-27", 1, 0)));
+
+        let tokyo = Exchange::Tse.timezone();
+        assert_eq!(9 * 3600, offset(tokyo, utc("2024-07-01", 0, 0)));
+
+        let local = date("2024-07-01").and_hms_opt(9, 30, 0).unwrap();
+        assert_eq!(utc("2024-07-01", 13, 30), to_utc(new_york, local));
+        assert_eq!(
+            local,
+            utc("2024-07-01", 13, 30)
+                .with_timezone(&new_york)
+                .naive_local()
+        );
+    }
+
+    #[test]
+    fn takes_skipped_and_repeated_times_as_standard_time() {
+        let new_york = Exchange::Nyse.timezone();
+        let local = |day, hour, minute| date(day).and_hms_opt(hour, minute, 0).unwrap();
+        assert_eq!(
+            utc("2024-03-10", 7, 30),
+            to_utc(new_york, local("2024-03-10", 2, 30))
+        );
+        assert_eq!(
+            utc("2024-11-03", 6, 30),
+            to_utc(new_york, local("2024-11-03", 1, 30))
+        );
+    }
+
+    #[test]
+    fn knows_when_the_exchange_is_open() {
+        let session = Exchange::Nyse.session();
+        // 9:30 in New York during daylight saving time
+        assert!(!session.is_open(utc("2024-07-01", 13, 29)));
+        assert!(session.is_open(utc("2024-07-01", 13, 30)));
+        assert!(!session.is_open(utc("2024-07-01", 20, 0)));
+        assert!(!session.is_open(utc("2024-07-04", 15, 0)));
+        // a Saturday
+        assert!(!session.is_open(utc("2024-07-06", 15, 0)));
+        // and standard time
+        assert!(session.is_open(utc("2024-12-02", 14, 30)));
+    }
+
+    #[test]
+    fn counts_trading_days() {
+        // 23 weekdays, less New Year's Day and Martin Luther King Jr. Day
+        assert_eq!(
+            21,
+            Exchange::Nyse.trading_days(date("2024-01-01"), date("2024-01-31"))
+        );
+        assert_eq!(
+            22,
+            Exchange::Xetra.trading_days(date("2024-01-01"), date("2024-01-31"))
+        );
+        assert_eq!(
+            0,
+            Exchange::Nyse.trading_days(date("2024-01-06"), date("2024-01-07"))
+        );
+        assert_eq!(
+            date("2023-12-29"),
+            Exchange::Nyse.trading_days_before(date("2024-01-03"), 3)
+        );
+        assert_eq!(
+            date("2024-01-02"),
+            Exchange::Nyse.trading_days_before(date("2024-01-02"), 1)
+        );
+    }
+
+    #[test]
+    fn counts_bars_per_day() {
+        let session = Exchange::Nyse.session();
+        assert_eq!(1, session.bars_per_day(Interval::Day));
+        assert_eq!(78, session.bars_per_day(Interval::FiveMinutes));
+        assert_eq!(7, session.bars_per_day(Interval::OneHour));
+    }
+
+    #[test]
+    fn finds_the_next_open() {
+        let mut session = Exchange::Nyse.session();
+        session.holidays.push(date("2024-07-05"));
+        let open = utc("2024-07-01", 15, 0);
+        assert_eq!(open, session.next_open(open));
+        assert_eq!(
+            utc("2024-07-01", 13, 30),
+            session.next_open(utc("2024-07-01", 3, 0))
+        );
+        // after Wednesday's close, past the holiday and an extra closure
+        assert_eq!(
+            utc("2024-07-08", 13, 30),
+            session.next_open(utc("2024-07-03", 21, 0))
+        );
+        // over a weekend, into standard time
+        assert_eq!(
+            utc("2024-11-04", 14, 30),
+            session.next_open(utc("2024-11-01", 21, 0))
+        );
+    }
+}
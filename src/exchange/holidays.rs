@@ -0,0 +1,383 @@
+//! The days each exchange is closed on besides weekends, from the rules for
+//! its regular holidays and a table of the one-off closures since 2000, or
+//! since 1989 in Tokyo.
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+use super::{last_weekday, nth_weekday, Exchange};
+
+/// Closures that don't follow any rule, such as national days of mourning.
+const CLOSURES: &[(Exchange, i32, u32, u32)] = &[
+    (Exchange::Nyse, 2001, 9, 11),
+    (Exchange::Nyse, 2001, 9, 12),
+    (Exchange::Nyse, 2001, 9, 13),
+    (Exchange::Nyse, 2001, 9, 14),
+    (Exchange::Nyse, 2004, 6, 11),
+    (Exchange::Nyse, 2007, 1, 2),
+    (Exchange::Nyse, 2012, 10, 29),
+    (Exchange::Nyse, 2012, 10, 30),
+    (Exchange::Nyse, 2018, 12, 5),
+    (Exchange::Nyse, 2025, 1, 9),
+    (Exchange::Lse, 2002, 6, 3),
+    (Exchange::Lse, 2011, 4, 29),
+    (Exchange::Lse, 2012, 6, 5),
+    (Exchange::Lse, 2022, 6, 3),
+    (Exchange::Lse, 2022, 9, 19),
+    (Exchange::Lse, 2023, 5, 8),
+    (Exchange::Tse, 1989, 2, 24),
+    (Exchange::Tse, 1990, 11, 12),
+    (Exchange::Tse, 1993, 6, 9),
+];
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn is_weekend(date: NaiveDate) -> bool {
+    matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// Easter Sunday in the Gregorian calendar.
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    date(year, month as u32, day as u32)
+}
+
+/// A US holiday on a Saturday is observed on the Friday before, and one on
+/// a Sunday on the Monday after.
+fn us_observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date - Duration::days(1),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+
+/// A UK holiday on a weekend is observed on the next weekday that isn't a
+/// holiday already.
+fn uk_observed(date: NaiveDate, holidays: &[NaiveDate]) -> NaiveDate {
+    let mut observed = date;
+    while is_weekend(observed) || holidays.contains(&observed) {
+        observed = observed.succ_opt().unwrap();
+    }
+    observed
+}
+
+fn nyse(year: i32) -> Vec<NaiveDate> {
+    let mut holidays = Vec::new();
+    // a New Year's Day on a Saturday isn't observed on the last trading day
+    // of the year before
+    let new_year = date(year, 1, 1);
+    if new_year.weekday() != Weekday::Sat {
+        holidays.push(us_observed(new_year));
+    }
+    holidays.push(nth_weekday(year, 1, Weekday::Mon, 3));
+    holidays.push(nth_weekday(year, 2, Weekday::Mon, 3));
+    holidays.push(easter(year) - Duration::days(2));
+    holidays.push(last_weekday(year, 5, Weekday::Mon));
+    if year >= 2022 {
+        holidays.push(us_observed(date(year, 6, 19)));
+    }
+    holidays.push(us_observed(date(year, 7, 4)));
+    holidays.push(nth_weekday(year, 9, Weekday::Mon, 1));
+    holidays.push(nth_weekday(year, 11, Weekday::Thu, 4));
+    holidays.push(us_observed(date(year, 12, 25)));
+    holidays
+}
+
+fn lse(year: i32) -> Vec<NaiveDate> {
+    let mut holidays = vec![
+        uk_observed(date(year, 1, 1), &[]),
+        easter(year) - Duration::days(2),
+        easter(year) + Duration::days(1),
+        match year {
+            // moved for the anniversary of VE day
+            2020 => date(2020, 5, 8),
+            _ => nth_weekday(year, 5, Weekday::Mon, 1),
+        },
+        match year {
+            // moved for the jubilees
+            2002 | 2012 => date(year, 6, 4),
+            2022 => date(2022, 6, 2),
+            _ => last_weekday(year, 5, Weekday::Mon),
+        },
+        last_weekday(year, 8, Weekday::Mon),
+    ];
+    let christmas = uk_observed(date(year, 12, 25), &holidays);
+    holidays.push(christmas);
+    holidays.push(uk_observed(date(year, 12, 26), &holidays));
+    holidays
+}
+
+fn xetra(year: i32) -> Vec<NaiveDate> {
+    vec![
+        date(year, 1, 1),
+        easter(year) - Duration::days(2),
+        easter(year) + Duration::days(1),
+        date(year, 5, 1),
+        date(year, 12, 24),
+        date(year, 12, 25),
+        date(year, 12, 26),
+        date(year, 12, 31),
+    ]
+}
+
+/// The day of the March or September equinox in Japan, between 1980 and
+/// 2099.
+fn japanese_equinox(year: i32, month: u32) -> NaiveDate {
+    let base = if month == 3 { 20.8431 } else { 23.2488 };
+    let years = (year - 1980) as f64;
+    let day = (base + 0.242_194 * years - (years / 4.0).floor()).floor();
+    date(year, month, day as u32)
+}
+
+/// Japan's national holidays since 1989, along with the Tokyo Stock
+/// Exchange's year-end closures.
+fn tse(year: i32) -> Vec<NaiveDate> {
+    let mut national = vec![
+        date(year, 1, 1),
+        date(year, 2, 11),
+        japanese_equinox(year, 3),
+        date(year, 4, 29),
+        date(year, 5, 3),
+        date(year, 5, 4),
+        date(year, 5, 5),
+        japanese_equinox(year, 9),
+        date(year, 11, 3),
+        date(year, 11, 23),
+    ];
+    // the Happy Monday rules moved several holidays to Mondays from 2000
+    national.push(if year >= 2000 {
+        nth_weekday(year, 1, Weekday::Mon, 2)
+    } else {
+        date(year, 1, 15)
+    });
+    national.push(if year >= 2003 {
+        nth_weekday(year, 9, Weekday::Mon, 3)
+    } else {
+        date(year, 9, 15)
+    });
+    match year {
+        // moved for the Olympics
+        2020 => national.extend(&[date(2020, 7, 23), date(2020, 7, 24), date(2020, 8, 10)]),
+        2021 => national.extend(&[date(2021, 7, 22), date(2021, 7, 23), date(2021, 8, 8)]),
+        _ => {
+            if year >= 2003 {
+                national.push(nth_weekday(year, 7, Weekday::Mon, 3));
+            } else if year >= 1996 {
+                national.push(date(year, 7, 20));
+            }
+            national.push(if year >= 2000 {
+                nth_weekday(year, 10, Weekday::Mon, 2)
+            } else {
+                date(year, 10, 10)
+            });
+            if year >= 2016 {
+                national.push(date(year, 8, 11));
+            }
+        }
+    }
+    // the Emperor's birthday, which there was none of in 2019
+    match year {
+        ..=2018 => national.push(date(year, 12, 23)),
+        2019 => national.extend(&[date(2019, 5, 1), date(2019, 10, 22)]),
+        _ => national.push(date(year, 2, 23)),
+    }
+    national.sort();
+    let mut holidays = national.clone();
+    // a day between two holidays is a holiday too
+    for pair in national.windows(2) {
+        if pair[1] - pair[0] == Duration::days(2) {
+            holidays.push(pair[0] + Duration::days(1));
+        }
+    }
+    // and a holiday on a Sunday moves to the next day that isn't one, or
+    // before 2007 to the Monday only if it wasn't one already
+    for holiday in national {
+        if holiday.weekday() == Weekday::Sun {
+            let mut substitute = holiday.succ_opt().unwrap();
+            while year >= 2007 && holidays.contains(&substitute) {
+                substitute = substitute.succ_opt().unwrap();
+            }
+            if !holidays.contains(&substitute) {
+                holidays.push(substitute);
+            }
+        }
+    }
+    holidays.extend(&[date(year, 1, 2), date(year, 1, 3), date(year, 12, 31)]);
+    holidays
+}
+
+/// Every weekday the exchange is closed on in a year, in no particular
+/// order.
+pub fn holidays(exchange: Exchange, year: i32) -> Vec<NaiveDate> {
+    let mut holidays = match exchange {
+        Exchange::Nyse | Exchange::Nasdaq => nyse(year),
+        Exchange::Lse => lse(year),
+        Exchange::Xetra => xetra(year),
+        Exchange::Tse => tse(year),
+    };
+    // NASDAQ closes whenever the NYSE does
+    let table = match exchange {
+        Exchange::Nasdaq => Exchange::Nyse,
+        exchange => exchange,
+    };
+    holidays.extend(
+        CLOSURES
+            .iter()
+            .filter(|closure| closure.0 == table && closure.1 == year)
+            .map(|closure| date(closure.1, closure.2, closure.3)),
+    );
+    holidays.retain(|holiday| !is_weekend(*holiday));
+    holidays
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::date;
+
+    fn sorted(exchange: Exchange, year: i32) -> Vec<String> {
+        let mut holidays = holidays(exchange, year);
+        holidays.sort();
+        holidays.dedup();
+        holidays.iter().map(|date| date.to_string()).collect()
+    }
+
+    #[test]
+    fn calculates_easter() {
+        assert_eq!(date("2024-03-31"), easter(2024));
+        assert_eq!(date("2025-04-20"), easter(2025));
+        assert_eq!(date("2019-04-21"), easter(2019));
+    }
+
+    #[test]
+    fn knows_nyse_holidays() {
+        assert_eq!(
+            vec![
+                "2024-01-01",
+                "2024-01-15",
+                "2024-02-19",
+                "2024-03-29",
+                "2024-05-27",
+                "2024-06-19",
+                "2024-07-04",
+                "2024-09-02",
+                "2024-11-28",
+                "2024-12-25",
+            ],
+            sorted(Exchange::Nyse, 2024)
+        );
+        // New Year's Day 2022 was a Saturday and Christmas a Sunday
+        let holidays = sorted(Exchange::Nasdaq, 2022);
+        assert!(!holidays.contains(&String::from("2021-12-31")));
+        assert!(holidays.contains(&String::from("2022-12-26")));
+        assert!(sorted(Exchange::Nasdaq, 2025).contains(&String::from("2025-01-09")));
+    }
+
+    #[test]
+    fn knows_lse_holidays() {
+        assert_eq!(
+            vec![
+                "2022-01-03",
+                "2022-04-15",
+                "2022-04-18",
+                "2022-05-02",
+                "2022-06-02",
+                "2022-06-03",
+                "2022-08-29",
+                "2022-09-19",
+                "2022-12-26",
+                "2022-12-27",
+            ],
+            sorted(Exchange::Lse, 2022)
+        );
+    }
+
+    #[test]
+    fn knows_xetra_holidays() {
+        assert_eq!(
+            vec![
+                "2024-01-01",
+                "2024-03-29",
+                "2024-04-01",
+                "2024-05-01",
+                "2024-12-24",
+                "2024-12-25",
+                "2024-12-26",
+                "2024-12-31",
+            ],
+            sorted(Exchange::Xetra, 2024)
+        );
+    }
+
+    #[test]
+    fn knows_tse_holidays() {
+        assert_eq!(
+            vec![
+                "2024-01-01",
+                "2024-01-02",
+                "2024-01-03",
+                "2024-01-08",
+                "2024-02-12",
+                "2024-02-23",
+                "2024-03-20",
+                "2024-04-29",
+                "2024-05-03",
+                "2024-05-06",
+                "2024-07-15",
+                "2024-08-12",
+                "2024-09-16",
+                "2024-09-23",
+                "2024-10-14",
+                "2024-11-04",
+                "2024-12-31",
+            ],
+            sorted(Exchange::Tse, 2024)
+        );
+        // the day between Respect for the Aged Day and the equinox
+        assert!(sorted(Exchange::Tse, 2026).contains(&String::from("2026-09-22")));
+    }
+
+    #[test]
+    fn knows_earlier_tse_holidays() {
+        let holidays = sorted(Exchange::Tse, 2018);
+        // the Emperor's birthday fell on a Sunday
+        assert!(holidays.contains(&String::from("2018-12-24")));
+        assert!(!holidays.contains(&String::from("2018-02-23")));
+        let holidays = sorted(Exchange::Tse, 2019);
+        for day in &[
+            "2019-04-29",
+            "2019-04-30",
+            "2019-05-01",
+            "2019-05-02",
+            "2019-05-03",
+            "2019-05-06",
+            "2019-10-22",
+        ] {
+            assert!(holidays.contains(&String::from(*day)), "{}", day);
+        }
+        assert!(!holidays.contains(&String::from("2019-12-23")));
+        // before the Happy Monday rules
+        let holidays = sorted(Exchange::Tse, 1998);
+        assert!(holidays.contains(&String::from("1998-01-15")));
+        assert!(holidays.contains(&String::from("1998-07-20")));
+        assert!(holidays.contains(&String::from("1998-09-15")));
+        assert!(holidays.contains(&String::from("1998-12-23")));
+        // and May 3rd on a Sunday had no substitute with May 4th a holiday
+        assert!(!holidays.contains(&String::from("1998-05-06")));
+    }
+}
@@ -1,46 +1,330 @@
 use std::f64;
 use std::fmt;
-use std::process;
+use std::rc::Rc;
+use std::sync::Arc;
 
 use actix::prelude::*;
-use async_trait::async_trait;
-use chrono::format::ParseError;
 use chrono::NaiveDate;
-use yahoo_finance_api as yahoo;
+use futures::stream::{self, LocalBoxStream, StreamExt};
+use serde::Serialize;
+
+pub mod alert;
+pub mod backtest;
+mod csv;
+mod error;
+pub mod exchange;
+mod output;
+pub mod portfolio;
+pub mod provider;
+pub mod risk;
+#[cfg(test)]
+mod testing;
+
+pub use alert::{
+    AlertEngine, AlertEvent, AlertSink, FileSink, RetryPolicy, Rule, StderrSink, WebhookSink,
+};
+pub use error::SstraError;
+pub use exchange::{Exchange, Session};
+pub use output::OutputFormat;
+pub use portfolio::{Holding, LedgerReport, LotMethod, PortfolioReport, Transaction};
+pub use provider::{
+    Bar, CachedProvider, FileProvider, Interval, PriceProvider, PriceSeries, YahooProvider,
+};
+pub use risk::{BenchmarkStatistics, CorrelationMatrix, RiskParameters, RiskStatistics};
+
+pub struct StockPriceFetcher {
+    provider: Rc<dyn PriceProvider>,
+}
 
-pub struct StockPriceFetcher;
 pub struct StockPriceProcessor;
 
-pub struct StockQuery<'a> {
+#[derive(Message)]
+#[rtype(result = "Result<StockPrices, SstraError>")]
+pub struct StockQuery {
     pub symbol: String,
-    pub period_start: String,
-    pub mov_avg_num_days: &'a i32,
+    pub period_start: NaiveDate,
+    /// The last day of the period, inclusive.
+    pub period_end: NaiveDate,
+    /// How long each bar the indicators are calculated on lasts.
+    pub interval: Interval,
+    /// Today's date on the symbol's exchange, which limits how far back
+    /// intraday bars go. Without it, the date is taken from the timezone the
+    /// symbol's prices come in.
+    pub today: Option<NaiveDate>,
+    pub indicators: Indicators,
 }
 
-#[async_trait(?Send)]
-impl<'a> Message for StockQuery<'a> {
-    type Result = Result<StockPrices<'static>, std::io::Error>;
+#[derive(Message)]
+#[rtype(result = "Result<StockInfo, SstraError>")]
+pub struct StockPrices {
+    pub symbol: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub interval: Interval,
+    pub currency: Option<String>,
+    pub bars: Vec<Bar>,
+    pub indicators: Indicators,
+    /// The benchmark to compare the prices with, shared by every symbol.
+    pub benchmark: Option<Arc<Benchmark>>,
 }
 
-#[derive(Message)]
-#[rtype(result = "Result<StockInfo, std::io::Error>")]
-pub struct StockPrices<'a> {
-    pub symbol: &'a String,
-    pub period_start: &'a String,
-    pub closing_prices: &'a Vec<f64>,
-    pub mov_avg_num_days: &'a i32,
+/// The prices of the symbol every other symbol is compared with.
+#[derive(Debug)]
+pub struct Benchmark {
+    pub symbol: String,
+    pub bars: Vec<Bar>,
 }
 
-#[derive(Message)]
-#[rtype(result = "Result<Self, std::io::Error>")]
+#[derive(Message, Serialize)]
+#[rtype(result = "Result<Self, SstraError>")]
 pub struct StockInfo {
     pub symbol: String,
-    pub period_start: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    /// How long each bar the indicators were calculated on lasts.
+    #[serde(skip_serializing_if = "Interval::is_day")]
+    pub interval: Interval,
+    pub currency: Option<String>,
     pub closing_price: f64,
+    /// The last close as it was quoted, without the adjustments for later
+    /// splits and dividends, which shares held then are valued at.
+    #[serde(skip)]
+    pub last_close: f64,
+    /// The change in price over the period, as a percentage.
+    #[serde(rename = "change_percent")]
     pub price_difference: f64,
     pub min: f64,
     pub max: f64,
-    pub simple_moving_average: f64,
+    /// The latest simple moving average for each window, as (days, average).
+    #[serde(rename = "sma", serialize_with = "output::serialize_windows")]
+    pub simple_moving_averages: Vec<(usize, f64)>,
+    /// The latest exponential moving average for each window.
+    #[serde(rename = "ema", serialize_with = "output::serialize_windows")]
+    pub exponential_moving_averages: Vec<(usize, f64)>,
+    /// The latest weighted moving average for each window.
+    #[serde(rename = "wma", serialize_with = "output::serialize_windows")]
+    pub weighted_moving_averages: Vec<(usize, f64)>,
+    /// The latest relative strength index, if requested.
+    #[serde(rename = "rsi", skip_serializing_if = "Option::is_none")]
+    pub relative_strength_index: Option<f64>,
+    /// The latest MACD values, if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub macd: Option<MacdInfo>,
+    /// The latest Bollinger Bands, if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bollinger: Option<BollingerBands>,
+    /// The risk statistics, if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub risk: Option<RiskStatistics>,
+    /// How the symbol performed relative to the benchmark, if requested and
+    /// the benchmark could be compared with it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub benchmark: Option<BenchmarkStatistics>,
+}
+
+/// Which indicators `StockPriceProcessor` calculates, and their parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Indicators {
+    /// The number of days in each simple moving average.
+    pub sma_windows: Vec<usize>,
+    /// The number of days in each exponential moving average.
+    pub ema_windows: Vec<usize>,
+    /// The smoothing factor of the exponential moving averages.
+    pub ema_smoothing: f64,
+    /// The number of days in each weighted moving average.
+    pub wma_windows: Vec<usize>,
+    /// The number of days the relative strength index is smoothed over.
+    pub rsi_period: Option<usize>,
+    /// The periods of the MACD, if it should be reported.
+    pub macd: Option<MacdPeriods>,
+    /// The window and width of the Bollinger Bands, if they should be
+    /// reported.
+    pub bollinger: Option<BollingerParameters>,
+    /// The parameters of the risk statistics, if they should be reported.
+    pub risk: Option<RiskParameters>,
+    /// The parameters of the statistics relative to the benchmark, if they
+    /// should be reported.
+    pub benchmark: Option<RiskParameters>,
+}
+
+/// The periods of the moving averages a MACD is made of, in days.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MacdPeriods {
+    pub fast: usize,
+    pub slow: usize,
+    pub signal: usize,
+}
+
+/// A full MACD series.
+///
+/// `signal` and `histogram` start later than `macd`, but all three end on
+/// the last price.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Macd {
+    /// The fast EMA minus the slow EMA.
+    pub macd: Vec<f64>,
+    /// The EMA of `macd`.
+    pub signal: Vec<f64>,
+    /// `macd` minus `signal`.
+    pub histogram: Vec<f64>,
+}
+
+/// The latest MACD values, as reported in `StockInfo`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MacdInfo {
+    pub macd: f64,
+    pub signal: f64,
+    pub histogram: f64,
+    /// Set if the MACD crossed its signal line on the last price.
+    pub crossover: Option<Crossover>,
+}
+
+/// The window of the moving average Bollinger Bands are built around, and
+/// how many standard deviations the bands are away from it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BollingerParameters {
+    pub window: usize,
+    pub width: f64,
+}
+
+/// Bollinger Bands for one price.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BollingerBands {
+    /// The simple moving average.
+    pub middle: f64,
+    pub upper: f64,
+    pub lower: f64,
+    /// Where the price sits between the lower (0) and upper (1) band.
+    pub percent_b: f64,
+    /// The distance between the bands relative to the middle one.
+    pub bandwidth: f64,
+}
+
+/// The direction the MACD crossed its signal line in.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Crossover {
+    /// The MACD rose above the signal line.
+    Bullish,
+    /// The MACD fell below the signal line.
+    Bearish,
+}
+
+impl Default for Indicators {
+    fn default() -> Self {
+        Indicators {
+            sma_windows: vec![30],
+            ema_windows: Vec::new(),
+            ema_smoothing: 2.0,
+            wma_windows: Vec::new(),
+            rsi_period: None,
+            macd: None,
+            bollinger: None,
+            risk: None,
+            benchmark: None,
+        }
+    }
+}
+
+impl Default for BollingerParameters {
+    fn default() -> Self {
+        BollingerParameters {
+            window: 20,
+            width: 2.0,
+        }
+    }
+}
+
+impl Default for MacdPeriods {
+    fn default() -> Self {
+        MacdPeriods {
+            fast: 12,
+            slow: 26,
+            signal: 9,
+        }
+    }
+}
+
+impl MacdPeriods {
+    /// The number of prices needed for the first signal value.
+    pub fn needed(&self) -> usize {
+        self.fast.max(self.slow) + self.signal - 1
+    }
+}
+
+impl Macd {
+    /// The latest values, or `None` if there aren't any signal values yet.
+    pub fn latest(&self) -> Option<MacdInfo> {
+        let histogram = *self.histogram.last()?;
+        let crossover = match self.histogram.len() {
+            0 | 1 => None,
+            len => {
+                let previous = self.histogram[len - 2];
+                if previous <= 0.0 && histogram > 0.0 {
+                    Some(Crossover::Bullish)
+                } else if previous >= 0.0 && histogram < 0.0 {
+                    Some(Crossover::Bearish)
+                } else {
+                    None
+                }
+            }
+        };
+        Some(MacdInfo {
+            macd: *self.macd.last()?,
+            signal: *self.signal.last()?,
+            histogram,
+            crossover,
+        })
+    }
+}
+
+impl Indicators {
+    /// The most days of prices any of the indicators needs.
+    pub fn longest_window(&self) -> usize {
+        // the RSI works on daily changes, so it needs one more price
+        let rsi_window = self.rsi_period.map(|period| period + 1);
+        let macd_window = self.macd.map(|periods| periods.needed());
+        let bollinger_window = self.bollinger.map(|bollinger| bollinger.window);
+        // the volatility needs at least two returns, i.e. three prices
+        let risk_window = self.risk.map(|_| 3);
+        let benchmark_window = self.benchmark.map(|_| 3);
+        self.sma_windows
+            .iter()
+            .chain(&self.ema_windows)
+            .chain(&self.wma_windows)
+            .copied()
+            .chain(rsi_window)
+            .chain(macd_window)
+            .chain(bollinger_window)
+            .chain(risk_window)
+            .chain(benchmark_window)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+impl StockPriceFetcher {
+    pub fn new(provider: Rc<dyn PriceProvider>) -> Self {
+        StockPriceFetcher { provider }
+    }
+}
+
+impl StockQuery {
+    pub fn new(
+        symbol: String,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        indicators: Indicators,
+    ) -> Self {
+        StockQuery {
+            symbol,
+            period_start,
+            period_end,
+            interval: Interval::Day,
+            today: None,
+            indicators,
+        }
+    }
 }
 
 impl Actor for StockPriceFetcher {
@@ -51,49 +335,262 @@ impl Actor for StockPriceProcessor {
     type Context = Context<Self>;
 }
 
-#[async_trait(?Send)]
-impl<'a> Handler<StockQuery<'a>> for StockPriceFetcher {
-    type Result = Result<StockPrices<'static>, std::io::Error>;
+impl Handler<StockQuery> for StockPriceFetcher {
+    type Result = ResponseFuture<Result<StockPrices, SstraError>>;
 
-    async fn handle(&mut self, msg: StockQuery<'static>, _ctx: &mut Self::Context) -> Self::Result {
-        let prices = get_closing_prices(&msg.symbol, &msg.period_start)
-            .await
-            .unwrap();
-        Ok(StockPrices {
-            symbol: &msg.symbol.to_string(),
-            period_start: &msg.period_start.to_string(),
-            closing_prices: &prices,
-            mov_avg_num_days: &msg.mov_avg_num_days,
+    fn handle(&mut self, msg: StockQuery, _ctx: &mut Self::Context) -> Self::Result {
+        let provider = Rc::clone(&self.provider);
+        Box::pin(async move {
+            if let Some(today) = msg.today {
+                msg.interval
+                    .check_period(msg.period_start, msg.period_end, today)?;
+            }
+            // risk statistics are annualized from daily returns
+            let indicators = &msg.indicators;
+            if !msg.interval.is_day()
+                && (indicators.risk.is_some() || indicators.benchmark.is_some())
+            {
+                return Err(SstraError::UnsupportedPeriod(format!(
+                    "risk statistics need daily bars, not {} ones",
+                    msg.interval
+                )));
+            }
+            let series = provider
+                .get_interval_series(&msg.symbol, msg.period_start, msg.period_end, msg.interval)
+                .await?;
+            if series.bars.is_empty() {
+                return Err(SstraError::EmptySeries(msg.symbol));
+            }
+            // without a date, the period is checked on the symbol's own,
+            // which only its prices tell
+            if let (None, Some(today)) = (msg.today, series.today()) {
+                msg.interval
+                    .check_period(msg.period_start, msg.period_end, today)?;
+            }
+            Ok(StockPrices {
+                symbol: msg.symbol,
+                period_start: msg.period_start,
+                period_end: msg.period_end,
+                interval: msg.interval,
+                currency: series.currency,
+                bars: series.bars,
+                indicators: msg.indicators,
+                benchmark: None,
+            })
         })
     }
 }
 
-#[async_trait(?Send)]
-impl<'a> Handler<StockPrices<'a>> for StockPriceProcessor {
-    type Result = Result<StockInfo, std::io::Error>;
+impl Handler<StockPrices> for StockPriceProcessor {
+    type Result = ResponseFuture<Result<StockInfo, SstraError>>;
 
-    async fn handle(&mut self, msg: StockPrices<'a>, _ctx: &mut Self::Context) -> Self::Result {
-        let prices = price_diff(&msg.closing_prices).await.unwrap();
-        let price_difference: f64 = prices.0;
-        let min = min(&msg.closing_prices).await.unwrap();
-        let max = max(&msg.closing_prices).await.unwrap();
-        let sma = *n_window_sma(*msg.mov_avg_num_days as usize, &msg.closing_prices)
-            .await
-            .unwrap()
-            .last()
-            .unwrap();
-        Ok(StockInfo {
-            symbol: msg.symbol.to_string(),
-            period_start: msg.period_start.to_string(),
-            closing_price: *msg.closing_prices.last().unwrap(),
-            price_difference: price_difference,
-            min: min,
-            max: max,
-            simple_moving_average: sma,
+    fn handle(&mut self, msg: StockPrices, _ctx: &mut Self::Context) -> Self::Result {
+        Box::pin(async move {
+            let closing_prices = closing_prices(&msg.bars);
+            let closing_price = match closing_prices.last() {
+                Some(price) => *price,
+                None => return Err(SstraError::EmptySeries(msg.symbol)),
+            };
+            let insufficient = |needed| SstraError::InsufficientData {
+                symbol: msg.symbol.clone(),
+                needed,
+                available: closing_prices.len(),
+            };
+            let indicators = &msg.indicators;
+            let mut simple_moving_averages = Vec::new();
+            for &window in &indicators.sma_windows {
+                let sma = latest(n_window_sma(window, &closing_prices).await)
+                    .ok_or_else(|| insufficient(window))?;
+                simple_moving_averages.push((window, sma));
+            }
+            let mut exponential_moving_averages = Vec::new();
+            for &window in &indicators.ema_windows {
+                let averages =
+                    n_window_ema(window, indicators.ema_smoothing, &closing_prices).await;
+                let ema = latest(averages).ok_or_else(|| insufficient(window))?;
+                exponential_moving_averages.push((window, ema));
+            }
+            let mut weighted_moving_averages = Vec::new();
+            for &window in &indicators.wma_windows {
+                let wma = latest(n_window_wma(window, &closing_prices).await)
+                    .ok_or_else(|| insufficient(window))?;
+                weighted_moving_averages.push((window, wma));
+            }
+            let relative_strength_index = match indicators.rsi_period {
+                Some(period) => Some(
+                    latest(n_window_rsi(period, &closing_prices).await)
+                        .ok_or_else(|| insufficient(period + 1))?,
+                ),
+                None => None,
+            };
+            let macd = match indicators.macd {
+                Some(periods) => Some(
+                    macd(periods, &closing_prices)
+                        .await
+                        .and_then(|macd| macd.latest())
+                        .ok_or_else(|| insufficient(periods.needed()))?,
+                ),
+                None => None,
+            };
+            let bollinger = match indicators.bollinger {
+                Some(parameters) => Some(
+                    bollinger_bands(parameters, &closing_prices)
+                        .await
+                        .and_then(|mut bands| bands.pop())
+                        .ok_or_else(|| insufficient(parameters.window))?,
+                ),
+                None => None,
+            };
+            let risk = match indicators.risk {
+                Some(parameters) => Some(
+                    risk::risk_statistics(parameters, &msg.bars)
+                        .await
+                        .ok_or_else(|| insufficient(3))?,
+                ),
+                None => None,
+            };
+            // the benchmark is missing if it couldn't be fetched, and its
+            // statistics if it has too few days in common with the symbol
+            let benchmark = match (indicators.benchmark, &msg.benchmark) {
+                (Some(parameters), Some(benchmark)) => {
+                    risk::benchmark_statistics(
+                        parameters,
+                        &msg.bars,
+                        &benchmark.symbol,
+                        &benchmark.bars,
+                    )
+                    .await
+                }
+                _ => None,
+            };
+            let prices = price_diff(&closing_prices)
+                .await
+                .ok_or_else(|| insufficient(1))?;
+            let price_difference: f64 = prices.0;
+            let min = min(&closing_prices).await.ok_or_else(|| insufficient(1))?;
+            let max = max(&closing_prices).await.ok_or_else(|| insufficient(1))?;
+            Ok(StockInfo {
+                symbol: msg.symbol,
+                period_start: msg.period_start,
+                period_end: msg.period_end,
+                interval: msg.interval,
+                currency: msg.currency,
+                closing_price,
+                // the prices were there to take the closing price from
+                last_close: msg.bars.last().unwrap().close,
+                price_difference,
+                min,
+                max,
+                simple_moving_averages,
+                exponential_moving_averages,
+                weighted_moving_averages,
+                relative_strength_index,
+                macd,
+                bollinger,
+                risk,
+                benchmark,
+            })
         })
     }
 }
 
+/// Sends every query through `fetcher` and `processor`, with at most
+/// `concurrency` symbols in flight at once.
+///
+/// Each result is paired with its symbol. Results are yielded as soon as they
+/// are ready, or in the order of `queries` if `ordered` is set.
+///
+/// A `benchmark` is fetched once, before any of the queries, and handed to
+/// the processor along with every symbol's prices. If it can't be fetched,
+/// its error is the first result and the symbols are processed without it.
+pub fn process_all(
+    fetcher: Addr<StockPriceFetcher>,
+    processor: Addr<StockPriceProcessor>,
+    queries: Vec<StockQuery>,
+    benchmark: Option<StockQuery>,
+    concurrency: usize,
+    ordered: bool,
+) -> LocalBoxStream<'static, (String, Result<StockInfo, SstraError>)> {
+    let results = async move {
+        let mut failures = Vec::new();
+        let benchmark = match benchmark {
+            Some(query) => {
+                let symbol = query.symbol.clone();
+                match fetcher.send(query).await {
+                    Ok(Ok(prices)) => Some(Arc::new(Benchmark {
+                        symbol,
+                        bars: prices.bars,
+                    })),
+                    Ok(Err(err)) => {
+                        failures.push((symbol, Err(err)));
+                        None
+                    }
+                    Err(err) => {
+                        failures.push((symbol, Err(err.into())));
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        let results = stream::iter(queries).map(move |query| {
+            let fetcher = fetcher.clone();
+            let processor = processor.clone();
+            let benchmark = benchmark.clone();
+            async move {
+                let symbol = query.symbol.clone();
+                (
+                    symbol,
+                    process(&fetcher, &processor, query, benchmark).await,
+                )
+            }
+        });
+        let concurrency = concurrency.max(1);
+        let results = if ordered {
+            results.buffered(concurrency).boxed_local()
+        } else {
+            results.buffer_unordered(concurrency).boxed_local()
+        };
+        stream::iter(failures).chain(results).boxed_local()
+    };
+    stream::once(results).flatten().boxed_local()
+}
+
+/// Sends every query through `fetcher` only, with at most `concurrency`
+/// symbols in flight at once, yielding each symbol's prices in the order of
+/// `queries`.
+pub fn fetch_all(
+    fetcher: Addr<StockPriceFetcher>,
+    queries: Vec<StockQuery>,
+    concurrency: usize,
+) -> LocalBoxStream<'static, (String, Result<StockPrices, SstraError>)> {
+    stream::iter(queries)
+        .map(move |query| {
+            let fetcher = fetcher.clone();
+            async move {
+                let symbol = query.symbol.clone();
+                let prices = match fetcher.send(query).await {
+                    Ok(prices) => prices,
+                    Err(err) => Err(err.into()),
+                };
+                (symbol, prices)
+            }
+        })
+        .buffered(concurrency.max(1))
+        .boxed_local()
+}
+
+async fn process(
+    fetcher: &Addr<StockPriceFetcher>,
+    processor: &Addr<StockPriceProcessor>,
+    query: StockQuery,
+    benchmark: Option<Arc<Benchmark>>,
+) -> Result<StockInfo, SstraError> {
+    let mut prices = fetcher.send(query).await??;
+    prices.benchmark = benchmark;
+    processor.send(prices).await?
+}
+
 impl actix::Supervised for StockPriceFetcher {
     fn restarting(&mut self, _ctx: &mut Context<StockPriceFetcher>) {
         println!("restarting");
@@ -106,47 +603,150 @@ impl actix::Supervised for StockPriceProcessor {
     }
 }
 
+impl StockInfo {
+    /// The header of the CSV lines of infos on bars of `interval`, whose
+    /// windows are labelled e.g. "30d" for daily bars or "30x5m" for intraday
+    /// ones.
+    pub fn csv_header(indicators: &Indicators, interval: Interval) -> String {
+        let unit = match interval {
+            Interval::Day => String::from("d"),
+            interval => format!("x{}", interval),
+        };
+        let mut header = String::from("period start,period end,symbol,price,change %,min,max");
+        for window in &indicators.sma_windows {
+            header.push_str(&format!(",{}{} avg", window, unit));
+        }
+        for window in &indicators.ema_windows {
+            header.push_str(&format!(",{}{} ema", window, unit));
+        }
+        for window in &indicators.wma_windows {
+            header.push_str(&format!(",{}{} wma", window, unit));
+        }
+        if let Some(period) = indicators.rsi_period {
+            header.push_str(&format!(",{}{} rsi", period, unit));
+        }
+        if indicators.macd.is_some() {
+            header.push_str(",macd,macd signal,macd histogram,macd crossover");
+        }
+        if indicators.bollinger.is_some() {
+            header.push_str(",bb middle,bb upper,bb lower,bb %b,bb bandwidth");
+        }
+        if indicators.risk.is_some() {
+            header.push_str(
+                ",volatility %,sharpe,sortino,max drawdown %,drawdown peak,drawdown trough,drawdown days",
+            );
+        }
+        if indicators.benchmark.is_some() {
+            header
+                .push_str(",benchmark,beta,alpha %,correlation,tracking error %,information ratio");
+        }
+        header
+    }
+}
+
 impl fmt::Display for StockInfo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "{},{},${:.2},{:.2}%,${:.2},${:.2},${:.2}",
+            "{},{},{},${:.2},{:.2}%,${:.2},${:.2}",
             self.period_start,
+            self.period_end,
             self.symbol,
             self.closing_price,
             self.price_difference,
             self.min,
             self.max,
-            self.simple_moving_average
-        )
+        )?;
+        let averages = self
+            .simple_moving_averages
+            .iter()
+            .chain(&self.exponential_moving_averages)
+            .chain(&self.weighted_moving_averages);
+        for (_, average) in averages {
+            write!(f, ",${:.2}", average)?;
+        }
+        if let Some(rsi) = self.relative_strength_index {
+            write!(f, ",{:.2}", rsi)?;
+        }
+        if let Some(macd) = &self.macd {
+            let crossover = match macd.crossover {
+                Some(Crossover::Bullish) => "bullish",
+                Some(Crossover::Bearish) => "bearish",
+                None => "",
+            };
+            write!(
+                f,
+                ",{:.2},{:.2},{:.2},{}",
+                macd.macd, macd.signal, macd.histogram, crossover
+            )?;
+        }
+        if let Some(bands) = &self.bollinger {
+            write!(
+                f,
+                ",${:.2},${:.2},${:.2},{:.4},{:.4}",
+                bands.middle, bands.upper, bands.lower, bands.percent_b, bands.bandwidth
+            )?;
+        }
+        if let Some(risk) = &self.risk {
+            write!(
+                f,
+                ",{:.2}%,{},{}",
+                risk.volatility * 100.0,
+                optional(risk.sharpe_ratio, ""),
+                optional(risk.sortino_ratio, ""),
+            )?;
+            match &risk.max_drawdown {
+                Some(drawdown) => write!(
+                    f,
+                    ",{:.2}%,{},{},{}",
+                    drawdown.depth * 100.0,
+                    drawdown.peak,
+                    drawdown.trough,
+                    drawdown.duration_days
+                )?,
+                None => write!(f, ",,,,")?,
+            }
+        }
+        if let Some(benchmark) = &self.benchmark {
+            write!(
+                f,
+                ",{},{},{},{},{:.2}%,{}",
+                benchmark.symbol,
+                optional(benchmark.beta, ""),
+                optional(benchmark.alpha.map(|alpha| alpha * 100.0), "%"),
+                optional(benchmark.correlation, ""),
+                benchmark.tracking_error * 100.0,
+                optional(benchmark.information_ratio, ""),
+            )?;
+        }
+        Ok(())
     }
 }
 
-async fn get_closing_prices(symbol: &str, period: &str) -> Option<Vec<f64>> {
-    let provider = yahoo::YahooConnector::new();
-    let response = provider
-        .get_quote_range(symbol, "1d", period)
-        .await
-        .unwrap_or_else(|err| {
-            eprintln!(
-                "Encountered a problem calling the Yahoo! Finance API: {:?}",
-                err
-            );
-            process::exit(1);
-        });
-    let quotes = response.quotes().unwrap();
-    let closing_prices: Vec<f64> = quotes.iter().map(|quote| quote.adjclose).collect();
+/// Formats a statistic that can't always be calculated, leaving it empty if
+/// it wasn't.
+fn optional(value: Option<f64>, suffix: &str) -> String {
+    value
+        .map(|value| format!("{:.2}{}", value, suffix))
+        .unwrap_or_default()
+}
 
-    Some(closing_prices)
+/// The adjusted closing prices of a series of bars.
+pub fn closing_prices(bars: &[Bar]) -> Vec<f64> {
+    bars.iter().map(|bar| bar.adjclose).collect()
 }
 
-pub fn count_days(from: &str, until: &str) -> Result<String, ParseError> {
-    let past = NaiveDate::parse_from_str(&from, "%Y-%m-%d")?;
-    let present = NaiveDate::parse_from_str(&until, "%Y-%m-%d")?;
-    let period = format!("{}", NaiveDate::signed_duration_since(present, past));
-    Ok(period.split("P").collect::<Vec<&str>>()[1]
-        .replace("D", "d")
-        .to_string())
+pub fn count_days(from: &str, until: &str) -> Result<String, SstraError> {
+    let past = parse_date(from)?;
+    let present = parse_date(until)?;
+    let period = NaiveDate::signed_duration_since(present, past);
+    Ok(format!("{}d", period.num_days()))
+}
+
+/// Parses a date in the form YYYY-MM-DD.
+pub fn parse_date(date: &str) -> Result<NaiveDate, SstraError> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|err| SstraError::InvalidDate(format!("{} ({})", date, err)))
 }
 
 pub async fn min(series: &[f64]) -> Option<f64> {
@@ -159,6 +759,9 @@ pub async fn max(series: &[f64]) -> Option<f64> {
 
 /// calculate the simple moving average of a series over a time period, n
 pub async fn n_window_sma(n: usize, series: &[f64]) -> Option<Vec<f64>> {
+    if n == 0 {
+        return None;
+    }
     let mut averages = Vec::<f64>::new();
     for subset in series.windows(n) {
         let length: f64 = subset.len() as f64;
@@ -168,6 +771,170 @@ pub async fn n_window_sma(n: usize, series: &[f64]) -> Option<Vec<f64>> {
     Some(averages)
 }
 
+/// calculate the population standard deviation of a series over a time
+/// period, n
+///
+/// Each value lines up with the simple moving average over the same window.
+pub async fn n_window_std_dev(n: usize, series: &[f64]) -> Option<Vec<f64>> {
+    if n == 0 {
+        return None;
+    }
+    let mut deviations = Vec::<f64>::new();
+    for subset in series.windows(n) {
+        let length: f64 = subset.len() as f64;
+        let avg = subset.iter().sum::<f64>() / length;
+        let variance = subset
+            .iter()
+            .map(|value| (value - avg).powi(2))
+            .sum::<f64>()
+            / length;
+        deviations.push(variance.sqrt());
+    }
+    Some(deviations)
+}
+
+/// calculate Bollinger Bands around the simple moving average of a series
+///
+/// A band without any width, i.e. over a flat series, puts every price in the
+/// middle, at a %B of 0.5.
+pub async fn bollinger_bands(
+    parameters: BollingerParameters,
+    series: &[f64],
+) -> Option<Vec<BollingerBands>> {
+    let averages = n_window_sma(parameters.window, series).await?;
+    let deviations = n_window_std_dev(parameters.window, series).await?;
+    // each band ends on the price its window ends on
+    let prices = &series[series.len() - averages.len()..];
+    let bands = averages
+        .iter()
+        .zip(&deviations)
+        .zip(prices)
+        .map(|((&middle, deviation), price)| {
+            let upper = middle + parameters.width * deviation;
+            let lower = middle - parameters.width * deviation;
+            let percent_b = if upper > lower {
+                (price - lower) / (upper - lower)
+            } else {
+                0.5
+            };
+            BollingerBands {
+                middle,
+                upper,
+                lower,
+                percent_b,
+                bandwidth: (upper - lower) / middle,
+            }
+        })
+        .collect();
+    Some(bands)
+}
+
+/// calculate the exponential moving average of a series over a time period, n
+///
+/// Each new value is weighted by `smoothing / (1 + n)`, so a smoothing of 2.0
+/// gives the usual EMA. The first average is the simple average of the first
+/// n values, so the result is the same length as `n_window_sma`'s.
+pub async fn n_window_ema(n: usize, smoothing: f64, series: &[f64]) -> Option<Vec<f64>> {
+    if n == 0 {
+        return None;
+    }
+    if series.len() < n {
+        return Some(Vec::new());
+    }
+    let weight = smoothing / (1.0 + n as f64);
+    let mut average = series[..n].iter().sum::<f64>() / n as f64;
+    let mut averages = vec![average];
+    for value in &series[n..] {
+        average = value * weight + average * (1.0 - weight);
+        averages.push(average);
+    }
+    Some(averages)
+}
+
+/// calculate the linearly weighted moving average of a series over a time
+/// period, n, where the most recent value has weight n and the oldest weight 1
+pub async fn n_window_wma(n: usize, series: &[f64]) -> Option<Vec<f64>> {
+    if n == 0 {
+        return None;
+    }
+    let total_weight = (n * (n + 1)) as f64 / 2.0;
+    let mut averages = Vec::<f64>::new();
+    for subset in series.windows(n) {
+        let weighted: f64 = subset
+            .iter()
+            .enumerate()
+            .map(|(i, value)| value * (i + 1) as f64)
+            .sum();
+        averages.push(weighted / total_weight);
+    }
+    Some(averages)
+}
+
+/// calculate the relative strength index of a series with Wilder's smoothing
+/// over a time period, n
+///
+/// The first value is based on the simple averages of the first n changes, so
+/// n + 1 values are needed for each result.
+pub async fn n_window_rsi(n: usize, series: &[f64]) -> Option<Vec<f64>> {
+    if n == 0 {
+        return None;
+    }
+    if series.len() <= n {
+        return Some(Vec::new());
+    }
+    let changes: Vec<f64> = series.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let mut average_gain = changes[..n].iter().map(|c| c.max(0.0)).sum::<f64>() / n as f64;
+    let mut average_loss = changes[..n].iter().map(|c| (-c).max(0.0)).sum::<f64>() / n as f64;
+    let rsi = |gain: f64, loss: f64| {
+        if loss == 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        }
+    };
+    let mut indices = vec![rsi(average_gain, average_loss)];
+    for change in &changes[n..] {
+        average_gain = (average_gain * (n - 1) as f64 + change.max(0.0)) / n as f64;
+        average_loss = (average_loss * (n - 1) as f64 + (-change).max(0.0)) / n as f64;
+        indices.push(rsi(average_gain, average_loss));
+    }
+    Some(indices)
+}
+
+/// calculate the moving average convergence/divergence of a series
+///
+/// The MACD line is the difference between the fast and slow EMAs, and the
+/// signal line is an EMA of the MACD line.
+pub async fn macd(periods: MacdPeriods, series: &[f64]) -> Option<Macd> {
+    if periods.fast == 0 || periods.slow == 0 || periods.signal == 0 {
+        return None;
+    }
+    let fast = n_window_ema(periods.fast, 2.0, series).await?;
+    let slow = n_window_ema(periods.slow, 2.0, series).await?;
+    // both averages end on the last price, so line up their ends
+    let len = fast.len().min(slow.len());
+    let macd: Vec<f64> = fast[fast.len() - len..]
+        .iter()
+        .zip(&slow[slow.len() - len..])
+        .map(|(fast, slow)| fast - slow)
+        .collect();
+    let signal = n_window_ema(periods.signal, 2.0, &macd).await?;
+    let histogram = macd[macd.len() - signal.len()..]
+        .iter()
+        .zip(&signal)
+        .map(|(macd, signal)| macd - signal)
+        .collect();
+    Some(Macd {
+        macd,
+        signal,
+        histogram,
+    })
+}
+
+fn latest(series: Option<Vec<f64>>) -> Option<f64> {
+    series?.last().copied()
+}
+
 pub fn percent_diff(first: f64, second: f64) -> Option<f64> {
     let diff = second - first;
     Some((diff * 100.0) / first)
@@ -178,9 +945,10 @@ pub fn percent_diff(first: f64, second: f64) -> Option<f64> {
 /// Returns a tuple of (percentage, absolute difference).
 /// The second value is absolute, i.e. against itself.
 pub async fn price_diff(series: &[f64]) -> Option<(f64, f64)> {
-    let days = series.len();
-    let percentage = percent_diff(series[0], series[days - 1]).unwrap();
-    let absolute = series[days - 1] - series[0];
+    let first = *series.first()?;
+    let last = *series.last()?;
+    let percentage = percent_diff(first, last)?;
+    let absolute = last - first;
 
     Some((percentage, absolute))
 }
@@ -188,6 +956,456 @@ pub async fn price_diff(series: &[f64]) -> Option<(f64, f64)> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::date;
+    use async_trait::async_trait;
+
+    struct StaticProvider(Vec<f64>);
+
+    #[async_trait(?Send)]
+    impl PriceProvider for StaticProvider {
+        async fn get_interval_series(
+            &self,
+            symbol: &str,
+            _start: NaiveDate,
+            _end: NaiveDate,
+            interval: Interval,
+        ) -> Result<PriceSeries, SstraError> {
+            if !interval.is_day() {
+                return Err(SstraError::Provider(format!(
+                    "No {} bars available for {}",
+                    interval, symbol
+                )));
+            }
+            let bars = self
+                .0
+                .iter()
+                .enumerate()
+                .map(|(i, &close)| Bar {
+                    timestamp: i as i64 * 86_400,
+                    open: close,
+                    high: close,
+                    low: close,
+                    close,
+                    adjclose: close,
+                    volume: 100,
+                    utc_offset: 0,
+                })
+                .collect();
+            Ok(PriceSeries {
+                bars,
+                ..Default::default()
+            })
+        }
+    }
+
+    #[actix_rt::test]
+    async fn processes_prices_from_provider() {
+        let provider = Rc::new(StaticProvider(vec![1.0, 2.0, 3.0, 4.0]));
+        let fetcher = StockPriceFetcher::new(provider).start();
+        let processor = StockPriceProcessor.start();
+        let query = StockQuery::new(
+            String::from("TEST"),
+            date("2020-01-01"),
+            date("2020-01-04"),
+            Indicators {
+                sma_windows: vec![2],
+                ..Default::default()
+            },
+        );
+        let prices = fetcher.send(query).await.unwrap().unwrap();
+        assert_eq!(4, prices.bars.len());
+        assert_eq!(100, prices.bars[0].volume);
+        let info = processor.send(prices).await.unwrap().unwrap();
+        assert_eq!(4.0, info.closing_price);
+        assert_eq!(300.0, info.price_difference);
+        assert_eq!(vec![(2, 3.5)], info.simple_moving_averages);
+    }
+
+    #[actix_rt::test]
+    async fn reports_insufficient_data_for_window() {
+        let provider = Rc::new(StaticProvider(vec![1.0, 2.0]));
+        let fetcher = StockPriceFetcher::new(provider).start();
+        let processor = StockPriceProcessor.start();
+        let query = StockQuery::new(
+            String::from("TEST"),
+            date("2020-01-01"),
+            date("2020-01-02"),
+            Indicators::default(),
+        );
+        let prices = fetcher.send(query).await.unwrap().unwrap();
+        match processor.send(prices).await.unwrap() {
+            Err(SstraError::InsufficientData {
+                needed, available, ..
+            }) => assert_eq!((30, 2), (needed, available)),
+            _ => panic!("expected insufficient data"),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn reports_empty_series() {
+        let provider = Rc::new(StaticProvider(Vec::new()));
+        let fetcher = StockPriceFetcher::new(provider).start();
+        let query = StockQuery::new(
+            String::from("TEST"),
+            date("2020-01-01"),
+            date("2020-01-02"),
+            Indicators::default(),
+        );
+        assert!(matches!(
+            fetcher.send(query).await.unwrap(),
+            Err(SstraError::EmptySeries(_))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn checks_intraday_periods_on_the_query_date() {
+        let fetcher = StockPriceFetcher::new(Rc::new(StaticProvider(vec![1.0]))).start();
+        let query = |today| StockQuery {
+            interval: Interval::OneMinute,
+            today: Some(date(today)),
+            ..StockQuery::new(
+                String::from("TEST"),
+                date("2020-01-02"),
+                date("2020-01-03"),
+                Indicators::default(),
+            )
+        };
+        assert!(matches!(
+            fetcher.send(query("2020-03-01")).await.unwrap(),
+            Err(SstraError::UnsupportedPeriod(_))
+        ));
+        // within the limit, so it's up to the provider, which only has days
+        assert!(matches!(
+            fetcher.send(query("2020-01-03")).await.unwrap(),
+            Err(SstraError::Provider(_))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn rejects_risk_statistics_on_intraday_bars() {
+        let fetcher = StockPriceFetcher::new(Rc::new(StaticProvider(vec![1.0]))).start();
+        let query = StockQuery {
+            interval: Interval::FiveMinutes,
+            today: Some(date("2020-01-03")),
+            ..StockQuery::new(
+                String::from("TEST"),
+                date("2020-01-02"),
+                date("2020-01-03"),
+                Indicators {
+                    risk: Some(RiskParameters::default()),
+                    ..Default::default()
+                },
+            )
+        };
+        assert!(matches!(
+            fetcher.send(query).await.unwrap(),
+            Err(SstraError::UnsupportedPeriod(_))
+        ));
+    }
+
+    /// Answers immediately, except for "SLOW", which takes a while.
+    struct DelayedProvider;
+
+    #[async_trait(?Send)]
+    impl PriceProvider for DelayedProvider {
+        async fn get_interval_series(
+            &self,
+            symbol: &str,
+            start: NaiveDate,
+            end: NaiveDate,
+            interval: Interval,
+        ) -> Result<PriceSeries, SstraError> {
+            if symbol == "SLOW" {
+                actix_rt::time::delay_for(std::time::Duration::from_millis(50)).await;
+            }
+            StaticProvider(vec![1.0, 2.0])
+                .get_interval_series(symbol, start, end, interval)
+                .await
+        }
+    }
+
+    async fn symbols_in_result_order(ordered: bool) -> Vec<String> {
+        let fetcher = StockPriceFetcher::new(Rc::new(DelayedProvider)).start();
+        let processor = StockPriceProcessor.start();
+        let queries = vec!["SLOW", "FAST"]
+            .into_iter()
+            .map(|symbol| {
+                StockQuery::new(
+                    String::from(symbol),
+                    date("2020-01-01"),
+                    date("2020-01-02"),
+                    Indicators {
+                        sma_windows: vec![1],
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        process_all(fetcher, processor, queries, None, 2, ordered)
+            .map(|(symbol, result)| {
+                assert!(result.is_ok());
+                symbol
+            })
+            .collect()
+            .await
+    }
+
+    #[actix_rt::test]
+    async fn compares_symbols_with_benchmark() {
+        let fetcher =
+            StockPriceFetcher::new(Rc::new(StaticProvider(vec![1.0, 2.0, 1.0, 2.0]))).start();
+        let processor = StockPriceProcessor.start();
+        let query = |symbol: &str| {
+            StockQuery::new(
+                String::from(symbol),
+                date("2020-01-01"),
+                date("2020-01-04"),
+                Indicators {
+                    sma_windows: Vec::new(),
+                    benchmark: Some(RiskParameters::default()),
+                    ..Default::default()
+                },
+            )
+        };
+        let results: Vec<_> = process_all(
+            fetcher,
+            processor,
+            vec![query("MSFT"), query("AAPL")],
+            Some(query("SPY")),
+            2,
+            true,
+        )
+        .collect()
+        .await;
+        assert_eq!(2, results.len());
+        for (_, result) in results {
+            let benchmark = result.unwrap().benchmark.unwrap();
+            assert_eq!("SPY", benchmark.symbol);
+            assert!((benchmark.beta.unwrap() - 1.0).abs() < 1e-9);
+            assert_eq!(0.0, benchmark.tracking_error);
+        }
+    }
+
+    /// Has prices for every symbol but "SPY".
+    struct NoBenchmarkProvider;
+
+    #[async_trait(?Send)]
+    impl PriceProvider for NoBenchmarkProvider {
+        async fn get_interval_series(
+            &self,
+            symbol: &str,
+            start: NaiveDate,
+            end: NaiveDate,
+            interval: Interval,
+        ) -> Result<PriceSeries, SstraError> {
+            if symbol == "SPY" {
+                return Err(SstraError::UnknownSymbol(symbol.to_string()));
+            }
+            StaticProvider(vec![1.0, 2.0, 1.0, 2.0])
+                .get_interval_series(symbol, start, end, interval)
+                .await
+        }
+    }
+
+    #[actix_rt::test]
+    async fn processes_symbols_without_missing_benchmark() {
+        let fetcher = StockPriceFetcher::new(Rc::new(NoBenchmarkProvider)).start();
+        let processor = StockPriceProcessor.start();
+        let query = |symbol: &str| {
+            StockQuery::new(
+                String::from(symbol),
+                date("2020-01-01"),
+                date("2020-01-04"),
+                Indicators {
+                    sma_windows: Vec::new(),
+                    benchmark: Some(RiskParameters::default()),
+                    ..Default::default()
+                },
+            )
+        };
+        let results: Vec<_> = process_all(
+            fetcher,
+            processor,
+            vec![query("MSFT"), query("AAPL")],
+            Some(query("SPY")),
+            2,
+            true,
+        )
+        .collect()
+        .await;
+        assert_eq!(3, results.len());
+        assert_eq!("SPY", results[0].0);
+        assert!(matches!(results[0].1, Err(SstraError::UnknownSymbol(_))));
+        for (_, result) in &results[1..] {
+            let info = result.as_ref().unwrap();
+            assert_eq!(2.0, info.closing_price);
+            assert!(info.benchmark.is_none());
+        }
+    }
+
+    /// Has prices for every symbol, but only the last two of "SPY"'s fall on
+    /// the same days as the others'.
+    struct LateBenchmarkProvider;
+
+    #[async_trait(?Send)]
+    impl PriceProvider for LateBenchmarkProvider {
+        async fn get_interval_series(
+            &self,
+            symbol: &str,
+            start: NaiveDate,
+            end: NaiveDate,
+            interval: Interval,
+        ) -> Result<PriceSeries, SstraError> {
+            let mut series = StaticProvider(vec![1.0, 2.0, 1.0, 2.0])
+                .get_interval_series(symbol, start, end, interval)
+                .await?;
+            if symbol == "SPY" {
+                for bar in &mut series.bars {
+                    bar.timestamp += 2 * 86_400;
+                }
+            }
+            Ok(series)
+        }
+    }
+
+    #[actix_rt::test]
+    async fn processes_symbols_without_enough_days_in_common_with_benchmark() {
+        let fetcher = StockPriceFetcher::new(Rc::new(LateBenchmarkProvider)).start();
+        let processor = StockPriceProcessor.start();
+        let query = |symbol: &str| {
+            StockQuery::new(
+                String::from(symbol),
+                date("2020-01-01"),
+                date("2020-01-06"),
+                Indicators {
+                    sma_windows: Vec::new(),
+                    benchmark: Some(RiskParameters::default()),
+                    ..Default::default()
+                },
+            )
+        };
+        let results: Vec<_> = process_all(
+            fetcher,
+            processor,
+            vec![query("AAPL")],
+            Some(query("SPY")),
+            1,
+            true,
+        )
+        .collect()
+        .await;
+        assert_eq!(1, results.len());
+        let info = results[0].1.as_ref().unwrap();
+        assert_eq!(2.0, info.closing_price);
+        assert!(info.benchmark.is_none());
+    }
+
+    #[actix_rt::test]
+    async fn processes_symbols_concurrently() {
+        assert_eq!(vec!["FAST", "SLOW"], symbols_in_result_order(false).await);
+        assert_eq!(vec!["SLOW", "FAST"], symbols_in_result_order(true).await);
+    }
+
+    #[test]
+    fn formats_one_column_per_window() {
+        let info = StockInfo {
+            period_start: date("2020-06-01"),
+            currency: Some(String::from("USD")),
+            price_difference: 11.634,
+            min: 134.37,
+            max: 231.05,
+            simple_moving_averages: vec![(50, 214.851), (200, 200.0)],
+            exponential_moving_averages: vec![(12, 218.0)],
+            weighted_moving_averages: vec![(20, 217.5)],
+            relative_strength_index: Some(61.234),
+            macd: Some(MacdInfo {
+                macd: 1.5,
+                signal: 1.25,
+                histogram: 0.25,
+                crossover: Some(Crossover::Bullish),
+            }),
+            bollinger: Some(BollingerBands {
+                middle: 215.0,
+                upper: 225.0,
+                lower: 205.0,
+                percent_b: 0.721,
+                bandwidth: 0.0930232,
+            }),
+            risk: Some(RiskStatistics {
+                volatility: 0.31234,
+                sharpe_ratio: Some(1.456),
+                sortino_ratio: None,
+                max_drawdown: Some(risk::Drawdown {
+                    depth: 0.2512,
+                    peak: date("2020-06-08"),
+                    trough: date("2020-09-08"),
+                    recovery: None,
+                    duration_days: 206,
+                }),
+            }),
+            benchmark: Some(BenchmarkStatistics {
+                symbol: String::from("SPY"),
+                beta: Some(1.1),
+                alpha: Some(0.0234),
+                correlation: None,
+                tracking_error: 0.05,
+                information_ratio: Some(0.5),
+            }),
+            ..testing::info("MSFT", 219.42)
+        };
+        let indicators = Indicators {
+            sma_windows: vec![50, 200],
+            ema_windows: vec![12],
+            wma_windows: vec![20],
+            rsi_period: Some(14),
+            macd: Some(MacdPeriods::default()),
+            bollinger: Some(BollingerParameters::default()),
+            risk: Some(RiskParameters::default()),
+            benchmark: Some(RiskParameters::default()),
+            ..Default::default()
+        };
+        assert_eq!(
+            "period start,period end,symbol,price,change %,min,max,50d avg,200d avg,12d ema,20d wma,14d rsi,macd,macd signal,macd histogram,macd crossover,bb middle,bb upper,bb lower,bb %b,bb bandwidth,volatility %,sharpe,sortino,max drawdown %,drawdown peak,drawdown trough,drawdown days,benchmark,beta,alpha %,correlation,tracking error %,information ratio",
+            StockInfo::csv_header(&indicators, Interval::Day)
+        );
+        assert_eq!(
+            "2020-06-01,2020-12-31,MSFT,$219.42,11.63%,$134.37,$231.05,$214.85,$200.00,$218.00,$217.50,61.23,1.50,1.25,0.25,bullish,$215.00,$225.00,$205.00,0.7210,0.0930,31.23%,1.46,,25.12%,2020-06-08,2020-09-08,206,SPY,1.10,2.34%,,5.00%,0.50",
+            info.to_string()
+        );
+        let intraday = Indicators {
+            sma_windows: vec![20],
+            rsi_period: Some(14),
+            ..Default::default()
+        };
+        assert_eq!(
+            "period start,period end,symbol,price,change %,min,max,20x5m avg,14x5m rsi",
+            StockInfo::csv_header(&intraday, Interval::FiveMinutes)
+        );
+    }
+
+    #[test]
+    fn serializes_raw_values() {
+        let info = StockInfo {
+            period_start: date("2020-06-01"),
+            currency: Some(String::from("EUR")),
+            price_difference: -2.25,
+            min: 90.0,
+            max: 140.0,
+            simple_moving_averages: vec![(20, 101.0), (50, 110.5)],
+            exponential_moving_averages: vec![(10, 104.0)],
+            macd: Some(MacdInfo {
+                macd: -0.5,
+                signal: 0.25,
+                histogram: -0.75,
+                crossover: None,
+            }),
+            ..testing::info("SAP.DE", 105.5)
+        };
+        assert_eq!(
+            r#"{"symbol":"SAP.DE","period_start":"2020-06-01","period_end":"2020-12-31","currency":"EUR","closing_price":105.5,"change_percent":-2.25,"min":90.0,"max":140.0,"sma":{"20":101.0,"50":110.5},"ema":{"10":104.0},"wma":{},"macd":{"macd":-0.5,"signal":0.25,"histogram":-0.75,"crossover":null}}"#,
+            serde_json::to_string(&info).unwrap()
+        );
+    }
 
     #[test]
     fn calculates_sma_over_3() {
@@ -198,6 +1416,113 @@ mod tests {
         );
     }
 
+    #[test]
+    fn calculates_std_dev() {
+        let x = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(
+            [2.0].to_vec(),
+            tokio_test::block_on(n_window_std_dev(8, &x)).unwrap()
+        );
+        let x = [1.0, 1.0, 1.0, 3.0];
+        assert_eq!(
+            [0.0, 0.0, 1.0].to_vec(),
+            tokio_test::block_on(n_window_std_dev(2, &x)).unwrap()
+        );
+    }
+
+    #[test]
+    fn calculates_bollinger_bands() {
+        let parameters = BollingerParameters {
+            window: 8,
+            width: 2.0,
+        };
+        let x = [1.0, 2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let bands = tokio_test::block_on(bollinger_bands(parameters, &x)).unwrap();
+        assert_eq!(2, bands.len());
+        assert_eq!(
+            BollingerBands {
+                middle: 5.0,
+                upper: 9.0,
+                lower: 1.0,
+                percent_b: 1.0,
+                bandwidth: 1.6,
+            },
+            bands[1]
+        );
+        let flat = tokio_test::block_on(bollinger_bands(parameters, &[3.0; 8])).unwrap();
+        assert_eq!(0.5, flat[0].percent_b);
+        assert_eq!(0.0, flat[0].bandwidth);
+    }
+
+    #[test]
+    fn calculates_ema_over_3() {
+        let x = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        assert_eq!(
+            [2.0, 3.0, 4.0, 5.0].to_vec(),
+            tokio_test::block_on(n_window_ema(3, 2.0, &x)).unwrap()
+        );
+        let x = [2.0, 2.0, 2.0, 6.0];
+        assert_eq!(
+            [2.0, 4.0].to_vec(),
+            tokio_test::block_on(n_window_ema(3, 2.0, &x)).unwrap()
+        );
+        assert_eq!(
+            [2.0, 3.0].to_vec(),
+            tokio_test::block_on(n_window_ema(3, 1.0, &x)).unwrap()
+        );
+    }
+
+    #[test]
+    fn calculates_wma_over_3() {
+        let x = [1.0, 2.0, 3.0, 6.0];
+        assert_eq!(
+            [14.0 / 6.0, 26.0 / 6.0].to_vec(),
+            tokio_test::block_on(n_window_wma(3, &x)).unwrap()
+        );
+    }
+
+    #[test]
+    fn calculates_rsi_over_2() {
+        let x = [1.0, 2.0, 1.0, 3.0, 3.0];
+        assert_eq!(
+            [50.0, 100.0 - 100.0 / 6.0, 100.0 - 100.0 / 6.0].to_vec(),
+            tokio_test::block_on(n_window_rsi(2, &x)).unwrap()
+        );
+        assert_eq!(
+            [100.0].to_vec(),
+            tokio_test::block_on(n_window_rsi(2, &[1.0, 2.0, 3.0])).unwrap()
+        );
+        assert_eq!(
+            Vec::<f64>::new(),
+            tokio_test::block_on(n_window_rsi(2, &[1.0, 2.0])).unwrap()
+        );
+    }
+
+    #[test]
+    fn calculates_macd() {
+        let periods = MacdPeriods {
+            fast: 1,
+            slow: 3,
+            signal: 3,
+        };
+        let x = [2.0, 2.0, 2.0, 8.0, 2.0, 5.5];
+        let series = tokio_test::block_on(macd(periods, &x)).unwrap();
+        assert_eq!(vec![0.0, 3.0, -1.5, 1.0], series.macd);
+        assert_eq!(vec![0.5, 0.75], series.signal);
+        assert_eq!(vec![-2.0, 0.25], series.histogram);
+        assert_eq!(
+            Some(MacdInfo {
+                macd: 1.0,
+                signal: 0.75,
+                histogram: 0.25,
+                crossover: Some(Crossover::Bullish),
+            }),
+            series.latest()
+        );
+        let series = tokio_test::block_on(macd(periods, &x[..4])).unwrap();
+        assert_eq!(None, series.latest());
+    }
+
     #[test]
     fn calculates_percent_difference() {
         assert_eq!(50.0, percent_diff(100.0, 150.0).unwrap())
@@ -217,6 +1542,7 @@ mod tests {
     fn calculates_price_difference() {
         let x = [1.0, 2.0, 3.0];
         assert_eq!((200.0, 2.0), tokio_test::block_on(price_diff(&x)).unwrap());
+        assert_eq!(None, tokio_test::block_on(price_diff(&[])));
     }
 
     #[test]
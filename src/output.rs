@@ -0,0 +1,39 @@
+use std::str::FromStr;
+
+use serde::ser::{SerializeMap, Serializer};
+
+/// How `StockInfo` results are written out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// One comma-separated line per symbol, after an optional header.
+    Csv,
+    /// A single JSON array holding every symbol's results.
+    Json,
+    /// One JSON object per line, written as soon as each symbol is ready.
+    Ndjson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!("Unknown output format {}", s)),
+        }
+    }
+}
+
+/// Writes (days, average) pairs as a map keyed by the number of days.
+pub(crate) fn serialize_windows<S: Serializer>(
+    windows: &[(usize, f64)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(windows.len()))?;
+    for (days, average) in windows {
+        map.serialize_entry(&days.to_string(), average)?;
+    }
+    map.end()
+}
@@ -0,0 +1,399 @@
+//! Aggregates the `StockInfo` of every position in a portfolio.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{csv, SstraError, StockInfo};
+
+pub mod ledger;
+
+pub use ledger::{LedgerReport, LotMethod, Transaction};
+
+/// A position read from a holdings file.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Holding {
+    pub symbol: String,
+    #[serde(alias = "qty", alias = "shares")]
+    pub quantity: f64,
+    /// What the whole position cost, not the price per share.
+    #[serde(alias = "cost", alias = "costBasis")]
+    pub cost_basis: f64,
+    /// The currency the cost basis is in, which has to be the quote currency
+    /// since positions aren't converted between currencies.
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+/// A position valued at the latest closing price.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Position {
+    pub symbol: String,
+    pub currency: Option<String>,
+    pub quantity: f64,
+    pub price: f64,
+    pub market_value: f64,
+    pub cost_basis: f64,
+    pub unrealized_pnl: f64,
+    pub unrealized_pnl_percent: f64,
+    /// The share of the market value of all positions in the same currency,
+    /// as a percentage.
+    pub weight: f64,
+    /// The change in price over the period, as a percentage.
+    pub period_return: f64,
+}
+
+/// The sum of all positions in one currency.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PortfolioTotal {
+    pub currency: Option<String>,
+    pub market_value: f64,
+    pub cost_basis: f64,
+    pub unrealized_pnl: f64,
+    pub unrealized_pnl_percent: f64,
+    /// The change in market value over the period, as a percentage, as if
+    /// the current positions had been held throughout.
+    pub period_return: f64,
+}
+
+/// A report on every position, with totals per currency since positions
+/// aren't converted between currencies.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PortfolioReport {
+    pub positions: Vec<Position>,
+    pub totals: Vec<PortfolioTotal>,
+}
+
+/// Reads holdings from a CSV or JSON file.
+///
+/// CSV files need a header row naming the `symbol`, `quantity` and
+/// `cost basis` columns, and optionally `currency`. JSON files hold an array
+/// of objects with the same fields.
+pub fn read_holdings<P: AsRef<Path>>(path: P) -> Result<Vec<Holding>, SstraError> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)?;
+    let holdings = match path.extension().and_then(|e| e.to_str()) {
+        Some(e) if e.eq_ignore_ascii_case("json") => {
+            serde_json::from_str(&contents).map_err(|err| err.to_string())
+        }
+        _ => parse_csv(&contents),
+    };
+    holdings.map_err(|err| SstraError::Provider(format!("{}: {}", path.display(), err)))
+}
+
+/// Reads holdings from a CSV file.
+fn parse_csv(contents: &str) -> Result<Vec<Holding>, String> {
+    csv::parse(contents, |row, fields| {
+        let mut holding = Holding {
+            symbol: String::new(),
+            quantity: 0.0,
+            cost_basis: 0.0,
+            currency: None,
+        };
+        for &(column, value) in fields {
+            let invalid = || csv::invalid(row, column, value);
+            match column {
+                "symbol" => holding.symbol = value.to_string(),
+                "quantity" | "qty" | "shares" => {
+                    holding.quantity = value.parse().map_err(|_| invalid())?
+                }
+                "costbasis" | "cost" => {
+                    holding.cost_basis = value.parse().map_err(|_| invalid())?
+                }
+                "currency" if !value.is_empty() => holding.currency = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        if holding.symbol.is_empty() {
+            return Err(format!("row {}: missing symbol", row));
+        }
+        Ok(holding)
+    })
+}
+
+/// Values `holdings` with the latest prices in `infos`, which are matched up
+/// by symbol.
+///
+/// Holdings of the same symbol are combined into one position. Holdings
+/// without a `StockInfo`, e.g. because their prices couldn't be fetched, are
+/// left out. A holding whose cost basis is in another currency than the
+/// symbol's quotes, or than another holding of the symbol, is an error.
+pub fn aggregate(holdings: &[Holding], infos: &[StockInfo]) -> Result<PortfolioReport, SstraError> {
+    let mut positions: Vec<Position> = Vec::new();
+    for holding in holdings {
+        let info = match infos
+            .iter()
+            .find(|info| info.symbol.eq_ignore_ascii_case(&holding.symbol))
+        {
+            Some(info) => info,
+            None => continue,
+        };
+        let mismatch = |currency: &str, other: &str| {
+            SstraError::CurrencyMismatch(format!(
+                "the cost basis of {} is in {}, but it's valued in {}",
+                info.symbol, currency, other
+            ))
+        };
+        if let (Some(currency), Some(quote)) = (&holding.currency, &info.currency) {
+            if !currency.eq_ignore_ascii_case(quote) {
+                return Err(mismatch(currency, quote));
+            }
+        }
+        if let Some(position) = positions
+            .iter_mut()
+            .find(|position| position.symbol == info.symbol)
+        {
+            if let (Some(currency), Some(merged)) = (&holding.currency, &position.currency) {
+                if !currency.eq_ignore_ascii_case(merged) {
+                    return Err(mismatch(currency, merged));
+                }
+            }
+            position.quantity += holding.quantity;
+            position.cost_basis += holding.cost_basis;
+            continue;
+        }
+        positions.push(Position {
+            symbol: info.symbol.clone(),
+            currency: info.currency.clone().or_else(|| holding.currency.clone()),
+            quantity: holding.quantity,
+            price: info.last_close,
+            market_value: 0.0,
+            cost_basis: holding.cost_basis,
+            unrealized_pnl: 0.0,
+            unrealized_pnl_percent: 0.0,
+            weight: 0.0,
+            period_return: info.price_difference,
+        });
+    }
+    for position in &mut positions {
+        position.market_value = position.quantity * position.price;
+        position.unrealized_pnl = position.market_value - position.cost_basis;
+        position.unrealized_pnl_percent = percent(position.unrealized_pnl, position.cost_basis);
+    }
+
+    let mut totals: Vec<PortfolioTotal> = Vec::new();
+    // the market value at the start of the period, per total
+    let mut start_values: Vec<f64> = Vec::new();
+    for position in &positions {
+        let index = match totals
+            .iter()
+            .position(|total| total.currency == position.currency)
+        {
+            Some(index) => index,
+            None => {
+                totals.push(PortfolioTotal {
+                    currency: position.currency.clone(),
+                    market_value: 0.0,
+                    cost_basis: 0.0,
+                    unrealized_pnl: 0.0,
+                    unrealized_pnl_percent: 0.0,
+                    period_return: 0.0,
+                });
+                start_values.push(0.0);
+                totals.len() - 1
+            }
+        };
+        totals[index].market_value += position.market_value;
+        totals[index].cost_basis += position.cost_basis;
+        start_values[index] += position.market_value / (1.0 + position.period_return / 100.0);
+    }
+    for (total, start_value) in totals.iter_mut().zip(start_values) {
+        total.unrealized_pnl = total.market_value - total.cost_basis;
+        total.unrealized_pnl_percent = percent(total.unrealized_pnl, total.cost_basis);
+        total.period_return = percent(total.market_value - start_value, start_value);
+    }
+    for position in &mut positions {
+        let total = totals
+            .iter()
+            .find(|total| total.currency == position.currency)
+            .map_or(0.0, |total| total.market_value);
+        position.weight = percent(position.market_value, total);
+    }
+
+    Ok(PortfolioReport { positions, totals })
+}
+
+/// `part` as a percentage of `whole`, or 0 if `whole` is.
+fn percent(part: f64, whole: f64) -> f64 {
+    if whole == 0.0 {
+        0.0
+    } else {
+        part * 100.0 / whole
+    }
+}
+
+impl PortfolioReport {
+    /// The CSV header matching the `Display` output.
+    pub fn csv_header() -> &'static str {
+        "symbol,currency,quantity,price,market value,cost basis,unrealized p&l,unrealized p&l %,weight %,period return %"
+    }
+}
+
+impl fmt::Display for PortfolioReport {
+    /// Writes one line per position, followed by one "total" line per
+    /// currency.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for position in &self.positions {
+            writeln!(
+                f,
+                "{},{},{},${:.2},${:.2},${:.2},${:.2},{:.2}%,{:.2}%,{:.2}%",
+                position.symbol,
+                position.currency.as_deref().unwrap_or_default(),
+                position.quantity,
+                position.price,
+                position.market_value,
+                position.cost_basis,
+                position.unrealized_pnl,
+                position.unrealized_pnl_percent,
+                position.weight,
+                position.period_return,
+            )?;
+        }
+        for total in &self.totals {
+            writeln!(
+                f,
+                "total,{},,,${:.2},${:.2},${:.2},{:.2}%,100.00%,{:.2}%",
+                total.currency.as_deref().unwrap_or_default(),
+                total.market_value,
+                total.cost_basis,
+                total.unrealized_pnl,
+                total.unrealized_pnl_percent,
+                total.period_return,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn info(symbol: &str, currency: &str, closing_price: f64, price_difference: f64) -> StockInfo {
+        StockInfo {
+            currency: Some(String::from(currency)),
+            price_difference,
+            ..testing::info(symbol, closing_price)
+        }
+    }
+
+    fn holding(symbol: &str, quantity: f64, cost_basis: f64) -> Holding {
+        Holding {
+            symbol: String::from(symbol),
+            quantity,
+            cost_basis,
+            currency: None,
+        }
+    }
+
+    #[test]
+    fn reads_csv_holdings() {
+        let path = testing::temp_path("holdings.csv");
+        fs::write(
+            &path,
+            "Symbol,Qty,Cost Basis,Currency\nMSFT,10,2000,USD\nSAP.DE,5,500,\n",
+        )
+        .unwrap();
+        let holdings = read_holdings(&path).unwrap();
+        assert_eq!(
+            vec![
+                Holding {
+                    currency: Some(String::from("USD")),
+                    ..holding("MSFT", 10.0, 2000.0)
+                },
+                holding("SAP.DE", 5.0, 500.0),
+            ],
+            holdings
+        );
+        fs::write(&path, "symbol,quantity\nMSFT,ten\n").unwrap();
+        assert!(matches!(read_holdings(&path), Err(SstraError::Provider(_))));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn values_positions() {
+        let holdings = vec![
+            holding("MSFT", 10.0, 2000.0),
+            holding("aapl", 20.0, 1000.0),
+            holding("MSFT", 5.0, 1000.0),
+            holding("SAP.DE", 10.0, 1200.0),
+            holding("GONE", 1.0, 100.0),
+        ];
+        let infos = vec![
+            info("AAPL", "USD", 100.0, 100.0),
+            info("MSFT", "USD", 200.0, 0.0),
+            info("SAP.DE", "EUR", 100.0, -50.0),
+        ];
+        let report = aggregate(&holdings, &infos).unwrap();
+
+        assert_eq!(
+            vec!["MSFT", "AAPL", "SAP.DE"],
+            report
+                .positions
+                .iter()
+                .map(|p| p.symbol.as_str())
+                .collect::<Vec<&str>>()
+        );
+        let msft = &report.positions[0];
+        assert_eq!(
+            (15.0, 3000.0, 3000.0),
+            (msft.quantity, msft.market_value, msft.cost_basis)
+        );
+        assert_eq!(0.0, msft.unrealized_pnl);
+        assert_eq!(60.0, msft.weight);
+        let aapl = &report.positions[1];
+        assert_eq!(
+            (1000.0, 100.0, 40.0),
+            (
+                aapl.unrealized_pnl,
+                aapl.unrealized_pnl_percent,
+                aapl.weight
+            )
+        );
+
+        assert_eq!(2, report.totals.len());
+        let usd = &report.totals[0];
+        assert_eq!(Some(String::from("USD")), usd.currency);
+        assert_eq!(
+            (5000.0, 4000.0, 1000.0),
+            (usd.market_value, usd.cost_basis, usd.unrealized_pnl)
+        );
+        // MSFT was worth 3000 at the start of the period and AAPL 1000
+        assert_eq!(25.0, usd.period_return);
+        let eur = &report.totals[1];
+        assert_eq!((1000.0, -200.0), (eur.market_value, eur.unrealized_pnl));
+        assert_eq!(-50.0, eur.period_return);
+        assert_eq!(100.0, report.positions[2].weight);
+    }
+
+    #[test]
+    fn rejects_cost_bases_in_other_currencies() {
+        let infos = vec![info("SAP.DE", "EUR", 100.0, 0.0)];
+        let in_dollars = Holding {
+            currency: Some(String::from("USD")),
+            ..holding("SAP.DE", 10.0, 1200.0)
+        };
+        assert!(matches!(
+            aggregate(std::slice::from_ref(&in_dollars), &infos),
+            Err(SstraError::CurrencyMismatch(_))
+        ));
+        let in_euros = Holding {
+            currency: Some(String::from("eur")),
+            ..holding("SAP.DE", 10.0, 1000.0)
+        };
+        assert!(aggregate(std::slice::from_ref(&in_euros), &infos).is_ok());
+
+        // without a quote currency, the holdings have to agree
+        let unknown = StockInfo {
+            currency: None,
+            ..info("SAP.DE", "EUR", 100.0, 0.0)
+        };
+        assert!(matches!(
+            aggregate(&[in_euros, in_dollars], &[unknown]),
+            Err(SstraError::CurrencyMismatch(_))
+        ));
+    }
+}
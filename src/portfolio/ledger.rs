@@ -0,0 +1,573 @@
+//! Reconstructs tax lots from a ledger of transactions.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{csv, SstraError, StockInfo};
+
+/// Quantities smaller than this are treated as nothing left.
+const EPSILON: f64 = 1e-9;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    Buy,
+    Sell,
+    Dividend,
+    Split,
+    Fee,
+}
+
+/// One entry in a ledger. Which fields are needed depends on the `kind`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Transaction {
+    pub date: NaiveDate,
+    #[serde(rename = "type")]
+    pub kind: TransactionKind,
+    /// Fees can be charged without a symbol.
+    #[serde(default)]
+    pub symbol: String,
+    /// The number of shares bought or sold.
+    #[serde(default)]
+    pub quantity: f64,
+    /// The price per share bought or sold at.
+    #[serde(default)]
+    pub price: f64,
+    /// The cash paid by a dividend or charged by a fee.
+    #[serde(default)]
+    pub amount: f64,
+    /// The commission of a buy or sell.
+    #[serde(default)]
+    pub fees: f64,
+    /// The number of new shares per old share of a split, e.g. 4 for a
+    /// 4-for-1 split.
+    #[serde(default)]
+    pub ratio: f64,
+    /// The lot a buy opens or a sell takes shares from.
+    #[serde(default)]
+    pub lot: Option<String>,
+}
+
+/// Which lots a sell takes shares from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LotMethod {
+    /// The oldest lots first.
+    Fifo,
+    /// The newest lots first.
+    Lifo,
+    /// The lot each sell names.
+    Specific,
+}
+
+impl FromStr for LotMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fifo" => Ok(LotMethod::Fifo),
+            "lifo" => Ok(LotMethod::Lifo),
+            "specific" => Ok(LotMethod::Specific),
+            _ => Err(format!("Unknown lot method {}", s)),
+        }
+    }
+}
+
+/// Shares bought together that haven't been sold yet.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Lot {
+    pub id: String,
+    pub symbol: String,
+    pub acquired: NaiveDate,
+    pub quantity: f64,
+    /// What the remaining shares cost, including fees.
+    pub cost_basis: f64,
+}
+
+/// Shares sold from one lot.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RealizedGain {
+    pub lot: String,
+    pub symbol: String,
+    pub acquired: NaiveDate,
+    pub sold: NaiveDate,
+    pub quantity: f64,
+    pub cost_basis: f64,
+    /// What the shares sold for, after fees.
+    pub proceeds: f64,
+    pub gain: f64,
+}
+
+/// A dividend paid or fee charged.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CashFlow {
+    pub date: NaiveDate,
+    pub symbol: String,
+    pub amount: f64,
+}
+
+/// The lots and gains left after replaying a ledger.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Ledger {
+    /// The open lots, in the order they were bought.
+    pub lots: Vec<Lot>,
+    pub realized: Vec<RealizedGain>,
+    pub dividends: Vec<CashFlow>,
+    pub fees: Vec<CashFlow>,
+}
+
+/// An open lot valued at the latest closing price.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LotValuation {
+    #[serde(flatten)]
+    pub lot: Lot,
+    /// `None` if the symbol's prices couldn't be fetched.
+    pub price: Option<f64>,
+    pub market_value: Option<f64>,
+    pub unrealized_gain: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LedgerReport {
+    pub open: Vec<LotValuation>,
+    pub realized: Vec<RealizedGain>,
+    pub dividends: Vec<CashFlow>,
+    pub fees: Vec<CashFlow>,
+}
+
+/// Reads a ledger from a CSV or JSON file.
+///
+/// CSV files need a header row naming the columns; `date` (YYYY-MM-DD),
+/// `type` (buy, sell, dividend, split or fee), `symbol`, `quantity`, `price`,
+/// `amount`, `fees`, `ratio` and `lot` are recognized, in any order. JSON
+/// files hold an array of objects with the same fields.
+pub fn read_ledger<P: AsRef<Path>>(path: P) -> Result<Vec<Transaction>, SstraError> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)?;
+    let transactions = match path.extension().and_then(|e| e.to_str()) {
+        Some(e) if e.eq_ignore_ascii_case("json") => {
+            serde_json::from_str(&contents).map_err(|err| err.to_string())
+        }
+        _ => parse_csv(&contents),
+    };
+    transactions.map_err(|err| SstraError::Provider(format!("{}: {}", path.display(), err)))
+}
+
+/// Reads transactions from a CSV file.
+fn parse_csv(contents: &str) -> Result<Vec<Transaction>, String> {
+    csv::parse(contents, |row, fields| {
+        let mut date = None;
+        let mut kind = None;
+        let mut transaction = Transaction {
+            date: NaiveDate::default(),
+            kind: TransactionKind::Fee,
+            symbol: String::new(),
+            quantity: 0.0,
+            price: 0.0,
+            amount: 0.0,
+            fees: 0.0,
+            ratio: 0.0,
+            lot: None,
+        };
+        for &(column, value) in fields {
+            let invalid = || csv::invalid(row, column, value);
+            let number = |value: &str| -> Result<f64, String> {
+                if value.is_empty() {
+                    Ok(0.0)
+                } else {
+                    value.parse().map_err(|_| invalid())
+                }
+            };
+            match column {
+                "date" => {
+                    date =
+                        Some(NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| invalid())?)
+                }
+                "type" | "kind" => {
+                    kind = Some(match value.to_lowercase().as_str() {
+                        "buy" => TransactionKind::Buy,
+                        "sell" => TransactionKind::Sell,
+                        "dividend" => TransactionKind::Dividend,
+                        "split" => TransactionKind::Split,
+                        "fee" => TransactionKind::Fee,
+                        _ => return Err(invalid()),
+                    })
+                }
+                "symbol" => transaction.symbol = value.to_string(),
+                "quantity" | "qty" | "shares" => transaction.quantity = number(value)?,
+                "price" => transaction.price = number(value)?,
+                "amount" => transaction.amount = number(value)?,
+                "fees" | "fee" | "commission" => transaction.fees = number(value)?,
+                "ratio" => transaction.ratio = number(value)?,
+                "lot" if !value.is_empty() => transaction.lot = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        transaction.date = date.ok_or_else(|| format!("row {}: missing date", row))?;
+        transaction.kind = kind.ok_or_else(|| format!("row {}: missing type", row))?;
+        Ok(transaction)
+    })
+}
+
+/// Replays every transaction up to and including `until`, in date order,
+/// keeping track of the open lots and what each sell realized.
+pub fn replay(
+    transactions: &[Transaction],
+    method: LotMethod,
+    until: NaiveDate,
+) -> Result<Ledger, SstraError> {
+    let mut transactions: Vec<&Transaction> =
+        transactions.iter().filter(|t| t.date <= until).collect();
+    // a stable sort keeps same-day transactions in the order they were given
+    transactions.sort_by_key(|t| t.date);
+
+    let mut ledger = Ledger::default();
+    // the number of buys of each symbol so far, to name their lots
+    let mut buys: HashMap<String, usize> = HashMap::new();
+    for transaction in transactions {
+        let invalid = |message: &str| {
+            SstraError::InvalidTransaction(format!(
+                "{} on {}: {}",
+                transaction.symbol, transaction.date, message
+            ))
+        };
+        let symbol = transaction.symbol.to_uppercase();
+        if symbol.is_empty() && transaction.kind != TransactionKind::Fee {
+            return Err(invalid("missing symbol"));
+        }
+        match transaction.kind {
+            TransactionKind::Buy => {
+                if transaction.quantity <= 0.0 {
+                    return Err(invalid("a buy needs a positive quantity"));
+                }
+                let count = buys.entry(symbol.clone()).or_insert(0);
+                *count += 1;
+                let id = match &transaction.lot {
+                    Some(id) => id.clone(),
+                    None => format!("{}#{}", symbol, count),
+                };
+                if ledger.lots.iter().any(|lot| lot.id == id) {
+                    return Err(invalid(&format!("lot {} is already open", id)));
+                }
+                ledger.lots.push(Lot {
+                    id,
+                    symbol,
+                    acquired: transaction.date,
+                    quantity: transaction.quantity,
+                    cost_basis: transaction.quantity * transaction.price + transaction.fees,
+                });
+            }
+            TransactionKind::Sell => {
+                if transaction.quantity <= 0.0 {
+                    return Err(invalid("a sell needs a positive quantity"));
+                }
+                let mut candidates: Vec<usize> = (0..ledger.lots.len())
+                    .filter(|&i| ledger.lots[i].symbol == symbol)
+                    .filter(|&i| match &transaction.lot {
+                        Some(id) => &ledger.lots[i].id == id,
+                        None => true,
+                    })
+                    .collect();
+                match (&transaction.lot, method) {
+                    (None, LotMethod::Specific) => return Err(invalid("a sell needs a lot")),
+                    (_, LotMethod::Lifo) => candidates.reverse(),
+                    _ => {}
+                }
+                let proceeds = transaction.quantity * transaction.price - transaction.fees;
+                let mut remaining = transaction.quantity;
+                for i in candidates {
+                    if remaining <= EPSILON {
+                        break;
+                    }
+                    let lot = &mut ledger.lots[i];
+                    let quantity = remaining.min(lot.quantity);
+                    let cost_basis = lot.cost_basis * quantity / lot.quantity;
+                    let share_of_proceeds = proceeds * quantity / transaction.quantity;
+                    lot.quantity -= quantity;
+                    lot.cost_basis -= cost_basis;
+                    remaining -= quantity;
+                    ledger.realized.push(RealizedGain {
+                        lot: lot.id.clone(),
+                        symbol: symbol.clone(),
+                        acquired: lot.acquired,
+                        sold: transaction.date,
+                        quantity,
+                        cost_basis,
+                        proceeds: share_of_proceeds,
+                        gain: share_of_proceeds - cost_basis,
+                    });
+                }
+                if remaining > EPSILON {
+                    return Err(invalid(&format!(
+                        "selling {} more shares than held",
+                        remaining
+                    )));
+                }
+                ledger.lots.retain(|lot| lot.quantity > EPSILON);
+            }
+            TransactionKind::Dividend => ledger.dividends.push(CashFlow {
+                date: transaction.date,
+                symbol,
+                amount: transaction.amount,
+            }),
+            TransactionKind::Split => {
+                if transaction.ratio <= 0.0 {
+                    return Err(invalid("a split needs a positive ratio"));
+                }
+                for lot in ledger.lots.iter_mut().filter(|lot| lot.symbol == symbol) {
+                    lot.quantity *= transaction.ratio;
+                }
+            }
+            TransactionKind::Fee => ledger.fees.push(CashFlow {
+                date: transaction.date,
+                symbol,
+                amount: transaction.amount,
+            }),
+        }
+    }
+    Ok(ledger)
+}
+
+impl Ledger {
+    /// The symbols of the open lots, in the order they were first bought.
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = Vec::new();
+        for lot in &self.lots {
+            if !symbols.contains(&lot.symbol) {
+                symbols.push(lot.symbol.clone());
+            }
+        }
+        symbols
+    }
+
+    /// Values the open lots at the last closes in `infos`, which are matched
+    /// up by symbol. Those are the prices as quoted at the end of the period,
+    /// which the lots' quantities were split up to.
+    pub fn report(self, infos: &[StockInfo]) -> LedgerReport {
+        let open = self
+            .lots
+            .into_iter()
+            .map(|lot| {
+                let price = infos
+                    .iter()
+                    .find(|info| info.symbol == lot.symbol)
+                    .map(|info| info.last_close);
+                let market_value = price.map(|price| price * lot.quantity);
+                LotValuation {
+                    unrealized_gain: market_value.map(|value| value - lot.cost_basis),
+                    lot,
+                    price,
+                    market_value,
+                }
+            })
+            .collect();
+        LedgerReport {
+            open,
+            realized: self.realized,
+            dividends: self.dividends,
+            fees: self.fees,
+        }
+    }
+}
+
+impl LedgerReport {
+    /// The CSV header matching the `Display` output.
+    pub fn csv_header() -> &'static str {
+        "status,lot,symbol,acquired,sold,quantity,cost basis,value,gain"
+    }
+}
+
+impl fmt::Display for LedgerReport {
+    /// Writes one line per open lot, then per realized gain, dividend and
+    /// fee. Fees count as negative gains.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let money = |value: Option<f64>| value.map(|v| format!("${:.2}", v)).unwrap_or_default();
+        for open in &self.open {
+            writeln!(
+                f,
+                "open,{},{},{},,{},${:.2},{},{}",
+                open.lot.id,
+                open.lot.symbol,
+                open.lot.acquired,
+                open.lot.quantity,
+                open.lot.cost_basis,
+                money(open.market_value),
+                money(open.unrealized_gain),
+            )?;
+        }
+        for gain in &self.realized {
+            writeln!(
+                f,
+                "realized,{},{},{},{},{},${:.2},${:.2},${:.2}",
+                gain.lot,
+                gain.symbol,
+                gain.acquired,
+                gain.sold,
+                gain.quantity,
+                gain.cost_basis,
+                gain.proceeds,
+                gain.gain,
+            )?;
+        }
+        for dividend in &self.dividends {
+            writeln!(
+                f,
+                "dividend,,{},,{},,,${:.2},${:.2}",
+                dividend.symbol, dividend.date, dividend.amount, dividend.amount,
+            )?;
+        }
+        for fee in &self.fees {
+            writeln!(
+                f,
+                "fee,,{},,{},,,${:.2},${:.2}",
+                fee.symbol, fee.date, fee.amount, -fee.amount,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, date};
+
+    fn ledger() -> Vec<Transaction> {
+        parse_csv(
+            "date,type,symbol,quantity,price,amount,fees,ratio,lot\n\
+             2020-01-02,buy,MSFT,10,100,,5,,\n\
+             2020-02-03,buy,MSFT,10,150,,5,,\n\
+             2020-03-02,dividend,MSFT,,,20,,,\n\
+             2020-04-01,sell,MSFT,15,200,,10,,\n\
+             2020-05-01,fee,,,,12,,,\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn realizes_oldest_lots_first() {
+        let ledger = replay(&ledger(), LotMethod::Fifo, date("2020-12-31")).unwrap();
+        assert_eq!(2, ledger.realized.len());
+        let first = &ledger.realized[0];
+        assert_eq!(
+            ("MSFT#1", 10.0, 1005.0),
+            (first.lot.as_str(), first.quantity, first.cost_basis)
+        );
+        // two thirds of the 2990 proceeds
+        assert!((first.gain - (2990.0 * 2.0 / 3.0 - 1005.0)).abs() < 1e-9);
+        assert_eq!(1, ledger.lots.len());
+        assert_eq!(
+            ("MSFT#2", 5.0, 752.5),
+            (
+                ledger.lots[0].id.as_str(),
+                ledger.lots[0].quantity,
+                ledger.lots[0].cost_basis
+            )
+        );
+        assert_eq!(20.0, ledger.dividends[0].amount);
+        assert_eq!(12.0, ledger.fees[0].amount);
+    }
+
+    #[test]
+    fn realizes_newest_lots_first() {
+        let ledger = replay(&ledger(), LotMethod::Lifo, date("2020-12-31")).unwrap();
+        assert_eq!(
+            vec![("MSFT#2", 10.0), ("MSFT#1", 5.0)],
+            ledger
+                .realized
+                .iter()
+                .map(|gain| (gain.lot.as_str(), gain.quantity))
+                .collect::<Vec<(&str, f64)>>()
+        );
+        assert_eq!(
+            ("MSFT#1", 5.0),
+            (ledger.lots[0].id.as_str(), ledger.lots[0].quantity)
+        );
+    }
+
+    #[test]
+    fn sells_named_lots() {
+        let mut transactions = ledger();
+        assert!(matches!(
+            replay(&transactions, LotMethod::Specific, date("2020-12-31")),
+            Err(SstraError::InvalidTransaction(_))
+        ));
+        transactions[1].lot = Some(String::from("second"));
+        transactions[3].lot = Some(String::from("second"));
+        transactions[3].quantity = 4.0;
+        let ledger = replay(&transactions, LotMethod::Specific, date("2020-12-31")).unwrap();
+        assert_eq!("second", ledger.realized[0].lot);
+        assert_eq!(
+            vec![10.0, 6.0],
+            ledger
+                .lots
+                .iter()
+                .map(|lot| lot.quantity)
+                .collect::<Vec<f64>>()
+        );
+    }
+
+    #[test]
+    fn splits_open_lots() {
+        let mut transactions = ledger();
+        transactions.push(Transaction {
+            date: date("2020-06-01"),
+            kind: TransactionKind::Split,
+            symbol: String::from("msft"),
+            quantity: 0.0,
+            price: 0.0,
+            amount: 0.0,
+            fees: 0.0,
+            ratio: 4.0,
+            lot: None,
+        });
+        let ledger = replay(&transactions, LotMethod::Fifo, date("2020-12-31")).unwrap();
+        assert_eq!(
+            (20.0, 752.5),
+            (ledger.lots[0].quantity, ledger.lots[0].cost_basis)
+        );
+        // the split is ignored before it happened
+        let ledger = replay(&transactions, LotMethod::Fifo, date("2020-05-31")).unwrap();
+        assert_eq!(5.0, ledger.lots[0].quantity);
+    }
+
+    #[test]
+    fn rejects_selling_more_than_held() {
+        let mut transactions = ledger();
+        transactions[3].quantity = 25.0;
+        assert!(matches!(
+            replay(&transactions, LotMethod::Fifo, date("2020-12-31")),
+            Err(SstraError::InvalidTransaction(_))
+        ));
+    }
+
+    #[test]
+    fn values_open_lots() {
+        let ledger = replay(&ledger(), LotMethod::Fifo, date("2020-12-31")).unwrap();
+        assert_eq!(vec!["MSFT"], ledger.symbols());
+        let report = ledger.report(&[]);
+        assert_eq!(None, report.open[0].market_value);
+        assert!(report
+            .to_string()
+            .starts_with("open,MSFT#2,MSFT,2020-02-03,,5,$752.50,,\n"));
+    }
+
+    #[test]
+    fn values_open_lots_as_quoted_before_later_splits() {
+        let ledger = replay(&ledger(), LotMethod::Fifo, date("2020-12-31")).unwrap();
+        // a 2:1 split in 2021 halved the adjusted closes of 2020
+        let info = StockInfo {
+            last_close: 220.0,
+            ..testing::info("MSFT", 110.0)
+        };
+        let report = ledger.report(&[info]);
+        assert_eq!(Some(220.0), report.open[0].price);
+        assert_eq!(Some(1100.0), report.open[0].market_value);
+        assert_eq!(Some(1100.0 - 752.5), report.open[0].unrealized_gain);
+    }
+}
@@ -0,0 +1,288 @@
+//! Sources of historical price data for `StockPriceFetcher`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::{exchange, SstraError};
+
+mod cache;
+mod file;
+mod yahoo;
+
+pub use cache::CachedProvider;
+pub use file::FileProvider;
+pub use yahoo::YahooProvider;
+
+/// A single OHLCV entry in a price series, as returned by a `PriceProvider`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bar {
+    /// Seconds since the Unix epoch at the start of the bar.
+    pub timestamp: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// The close adjusted for splits and dividends.
+    pub adjclose: f64,
+    pub volume: u64,
+    /// The exchange's offset from UTC at the start of the bar, in seconds
+    /// east.
+    #[serde(default)]
+    pub utc_offset: i32,
+}
+
+impl Bar {
+    /// When the bar starts, in the exchange's local time.
+    pub fn local_time(&self) -> DateTime<FixedOffset> {
+        let offset =
+            FixedOffset::east_opt(self.utc_offset).unwrap_or(FixedOffset::east_opt(0).unwrap());
+        DateTime::from_timestamp(self.timestamp, 0)
+            .unwrap_or_default()
+            .with_timezone(&offset)
+    }
+
+    /// The day the bar starts on, in the exchange's local time, so that the
+    /// bars of exchanges in different timezones line up by trading day.
+    pub fn date(&self) -> NaiveDate {
+        self.local_time().date_naive()
+    }
+}
+
+/// How long each bar in a series lasts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum Interval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[default]
+    #[serde(rename = "1d")]
+    Day,
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "1m" => Ok(Interval::OneMinute),
+            "5m" => Ok(Interval::FiveMinutes),
+            "15m" => Ok(Interval::FifteenMinutes),
+            "1h" | "60m" => Ok(Interval::OneHour),
+            "1d" => Ok(Interval::Day),
+            _ => Err(format!("Unknown interval {}", s)),
+        }
+    }
+}
+
+impl Interval {
+    /// The interval as Yahoo! Finance names it.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::OneMinute => "1m",
+            Interval::FiveMinutes => "5m",
+            Interval::FifteenMinutes => "15m",
+            Interval::OneHour => "1h",
+            Interval::Day => "1d",
+        }
+    }
+
+    pub fn is_day(&self) -> bool {
+        *self == Interval::Day
+    }
+
+    /// The length of a bar in minutes, or `None` for daily bars.
+    pub fn minutes(&self) -> Option<i64> {
+        match self {
+            Interval::OneMinute => Some(1),
+            Interval::FiveMinutes => Some(5),
+            Interval::FifteenMinutes => Some(15),
+            Interval::OneHour => Some(60),
+            Interval::Day => None,
+        }
+    }
+
+    /// Checks that bars of this interval can be had for every day from
+    /// `start` until `end` on `today`. Yahoo! Finance only keeps 30 days of
+    /// 1 minute bars, and serves at most 8 days of them at a time, 60 days of
+    /// 5 and 15 minute bars and 730 days of hourly ones.
+    pub fn check_period(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        today: NaiveDate,
+    ) -> Result<(), SstraError> {
+        let (history, span) = match self {
+            Interval::OneMinute => (30, 7),
+            Interval::FiveMinutes | Interval::FifteenMinutes => (60, 60),
+            Interval::OneHour => (730, 730),
+            Interval::Day => return Ok(()),
+        };
+        if (today - start).num_days() >= history {
+            return Err(SstraError::UnsupportedPeriod(format!(
+                "{} bars only go back {} days, but the period starts on {}",
+                self, history, start
+            )));
+        }
+        if (end - start).num_days() > span {
+            return Err(SstraError::UnsupportedPeriod(format!(
+                "{} bars can only be fetched {} days at a time",
+                self,
+                span + 1
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A symbol's bars along with what is known about the series as a whole.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PriceSeries {
+    /// The currency the prices are quoted in, e.g. "USD".
+    pub currency: Option<String>,
+    /// The timezone of the symbol's exchange, e.g. "Asia/Tokyo".
+    pub timezone: Option<Tz>,
+    pub bars: Vec<Bar>,
+}
+
+impl PriceSeries {
+    /// Today's date on the symbol's exchange: in its timezone if that's
+    /// known, or else at the offset of the last bar.
+    pub fn today(&self) -> Option<NaiveDate> {
+        local_today(self.timezone, self.bars.last())
+    }
+}
+
+pub(crate) fn local_today(timezone: Option<Tz>, last: Option<&Bar>) -> Option<NaiveDate> {
+    match (timezone, last) {
+        (Some(timezone), _) => Some(exchange::today(timezone)),
+        (None, Some(bar)) => {
+            let offset = FixedOffset::east_opt(bar.utc_offset)?;
+            Some(Utc::now().with_timezone(&offset).date_naive())
+        }
+        (None, None) => None,
+    }
+}
+
+/// Anything that can return a series of bars for a symbol.
+///
+/// Bars are returned in chronological order, covering every day from `start`
+/// up to and including `end`. Providers only need to implement
+/// `get_interval_series`; providers without intraday bars return a
+/// `SstraError::Provider` for any interval but `Interval::Day`.
+#[async_trait(?Send)]
+pub trait PriceProvider {
+    /// The series of bars of `interval`, along with its currency if the
+    /// provider knows it.
+    async fn get_interval_series(
+        &self,
+        symbol: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+        interval: Interval,
+    ) -> Result<PriceSeries, SstraError>;
+
+    /// Like `get_interval_series`, with daily bars.
+    async fn get_series(
+        &self,
+        symbol: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<PriceSeries, SstraError> {
+        self.get_interval_series(symbol, start, end, Interval::Day)
+            .await
+    }
+
+    /// Like `get_series`, without the currency.
+    async fn get_bars(
+        &self,
+        symbol: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<Bar>, SstraError> {
+        Ok(self.get_series(symbol, start, end).await?.bars)
+    }
+}
+
+/// Returns the timestamp of midnight UTC at the start of `date`.
+pub(crate) fn day_start(date: NaiveDate) -> i64 {
+    date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp()
+}
+
+/// Whether a bar starts on any day from `start` up to and including `end`, in
+/// the exchange's local time.
+pub(crate) fn within(bar: &Bar, start: NaiveDate, end: NaiveDate) -> bool {
+    let date = bar.date();
+    start <= date && date <= end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::date;
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(Ok(Interval::FiveMinutes), "5m".parse());
+        assert_eq!(Ok(Interval::OneHour), "60m".parse());
+        assert_eq!(Interval::Day, Interval::default());
+        assert!("2d".parse::<Interval>().is_err());
+        assert_eq!("15m", Interval::FifteenMinutes.to_string());
+    }
+
+    #[test]
+    fn limits_intraday_periods() {
+        let today = date("2024-06-28");
+        assert!(Interval::Day
+            .check_period(date("2000-01-03"), today, today)
+            .is_ok());
+        assert!(Interval::FiveMinutes
+            .check_period(date("2024-05-01"), today, today)
+            .is_ok());
+        assert!(matches!(
+            Interval::FiveMinutes.check_period(date("2024-04-01"), today, today),
+            Err(SstraError::UnsupportedPeriod(_))
+        ));
+        assert!(Interval::OneMinute
+            .check_period(date("2024-06-21"), today, today)
+            .is_ok());
+        assert!(matches!(
+            Interval::OneMinute.check_period(date("2024-06-10"), date("2024-06-20"), today),
+            Err(SstraError::UnsupportedPeriod(_))
+        ));
+    }
+
+    #[test]
+    fn finds_today_where_the_series_trades() {
+        let tokyo = PriceSeries {
+            timezone: Some(Tz::Asia__Tokyo),
+            ..Default::default()
+        };
+        assert_eq!(Some(exchange::today(Tz::Asia__Tokyo)), tokyo.today());
+        // Tokyo doesn't observe daylight saving time
+        let offset = PriceSeries {
+            bars: vec![Bar {
+                utc_offset: 9 * 3600,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(tokyo.today(), offset.today());
+        assert_eq!(None, PriceSeries::default().today());
+    }
+}
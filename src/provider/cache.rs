@@ -0,0 +1,309 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use super::{local_today, within, Bar, Interval, PriceProvider, PriceSeries};
+use crate::SstraError;
+
+/// Keeps a per-symbol copy of the bars returned by another provider in
+/// `directory`, so that later requests only fetch the days that aren't cached
+/// yet.
+///
+/// Days that hadn't closed yet when they were fetched are always fetched
+/// again.
+pub struct CachedProvider {
+    directory: PathBuf,
+    inner: Box<dyn PriceProvider>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheEntry {
+    /// The first day covered by the cached bars.
+    start: NaiveDate,
+    /// The last day covered by the cached bars that had already closed.
+    end: NaiveDate,
+    #[serde(default)]
+    currency: Option<String>,
+    #[serde(default)]
+    timezone: Option<Tz>,
+    bars: Vec<Bar>,
+}
+
+impl CacheEntry {
+    /// Replaces any cached bars from `start` to `end` with `fresh` ones.
+    fn merge(&mut self, fresh: PriceSeries, start: NaiveDate, end: NaiveDate) {
+        if fresh.currency.is_some() {
+            self.currency = fresh.currency;
+        }
+        if fresh.timezone.is_some() {
+            self.timezone = fresh.timezone;
+        }
+        self.bars.retain(|bar| !within(bar, start, end));
+        self.bars.extend(fresh.bars);
+        self.bars.sort_by_key(|bar| bar.timestamp);
+    }
+
+    /// The last day that has closed on the symbol's exchange. Without bars
+    /// or a timezone to tell where that is, it's the day before yesterday in
+    /// UTC, which has closed everywhere.
+    fn last_closed(&self) -> NaiveDate {
+        let day = Duration::days(1);
+        match local_today(self.timezone, self.bars.last()) {
+            Some(today) => today - day,
+            None => Utc::now().date_naive() - day * 2,
+        }
+    }
+}
+
+impl CachedProvider {
+    pub fn new<P: Into<PathBuf>>(directory: P, inner: Box<dyn PriceProvider>) -> Self {
+        CachedProvider {
+            directory: directory.into(),
+            inner,
+        }
+    }
+
+    fn path(&self, symbol: &str, interval: Interval) -> PathBuf {
+        self.directory
+            .join(format!("{}-{}.json", symbol.to_uppercase(), interval))
+    }
+
+    /// Fetches the days missing from a cached entry, which may well have no
+    /// bars at all, e.g. a weekend or a holiday.
+    async fn fetch_missing(
+        &self,
+        symbol: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+        interval: Interval,
+    ) -> Result<PriceSeries, SstraError> {
+        match self
+            .inner
+            .get_interval_series(symbol, start, end, interval)
+            .await
+        {
+            Err(SstraError::EmptySeries(_)) => Ok(PriceSeries::default()),
+            result => result,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl PriceProvider for CachedProvider {
+    async fn get_interval_series(
+        &self,
+        symbol: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+        interval: Interval,
+    ) -> Result<PriceSeries, SstraError> {
+        let day = Duration::days(1);
+        let path = self.path(symbol, interval);
+
+        let mut entry = match read_entry(&path)? {
+            // a gap between the cached days and the requested ones can't be
+            // represented, so those start over
+            Some(entry) if start <= entry.end + day && end + day >= entry.start => entry,
+            _ => {
+                let series = self
+                    .inner
+                    .get_interval_series(symbol, start, end, interval)
+                    .await?;
+                let mut entry = CacheEntry {
+                    start,
+                    end,
+                    currency: series.currency.clone(),
+                    timezone: series.timezone,
+                    bars: series.bars.clone(),
+                };
+                entry.end = end.min(entry.last_closed());
+                write_entry(&path, &entry)?;
+                return Ok(series);
+            }
+        };
+        if start < entry.start {
+            let before = entry.start - day;
+            let fresh = self.fetch_missing(symbol, start, before, interval).await?;
+            entry.merge(fresh, start, before);
+            entry.start = start;
+        }
+        if end > entry.end {
+            let after = entry.end + day;
+            let fresh = self.fetch_missing(symbol, after, end, interval).await?;
+            entry.merge(fresh, after, end);
+            entry.end = end.min(entry.last_closed());
+        }
+        write_entry(&path, &entry)?;
+
+        Ok(PriceSeries {
+            currency: entry.currency,
+            timezone: entry.timezone,
+            bars: entry
+                .bars
+                .into_iter()
+                .filter(|bar| within(bar, start, end))
+                .collect(),
+        })
+    }
+}
+
+fn read_entry(path: &Path) -> Result<Option<CacheEntry>, io::Error> {
+    match fs::read_to_string(path) {
+        // an unreadable entry, e.g. one written by an older version, is
+        // simply fetched again
+        Ok(contents) => Ok(serde_json::from_str(&contents).ok()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn write_entry(path: &Path, entry: &CacheEntry) -> Result<(), io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let contents = serde_json::to_string(entry)?;
+    // write to a temporary file first so an interrupted run can't leave a
+    // truncated cache behind
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::day_start;
+    use crate::testing::{date, temp_path};
+    use chrono::Datelike;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Requests = Rc<RefCell<Vec<(NaiveDate, NaiveDate)>>>;
+
+    /// Serves one bar per day up to today, or only on weekdays, and records
+    /// each request. Like Yahoo! Finance, it has no empty series.
+    struct DailyProvider {
+        requests: Requests,
+        weekdays: bool,
+    }
+
+    #[async_trait(?Send)]
+    impl PriceProvider for DailyProvider {
+        async fn get_interval_series(
+            &self,
+            _symbol: &str,
+            start: NaiveDate,
+            end: NaiveDate,
+            _interval: Interval,
+        ) -> Result<PriceSeries, SstraError> {
+            self.requests.borrow_mut().push((start, end));
+            let end = end.min(Utc::now().date_naive());
+            let bars: Vec<Bar> = start
+                .iter_days()
+                .take_while(|day| *day <= end)
+                .filter(|day| !self.weekdays || day.weekday().number_from_monday() <= 5)
+                .map(|day| Bar {
+                    timestamp: day_start(day),
+                    adjclose: 1.0,
+                    ..Default::default()
+                })
+                .collect();
+            if bars.is_empty() {
+                return Err(SstraError::EmptySeries(String::from("TEST")));
+            }
+            Ok(PriceSeries {
+                bars,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn provider(name: &str) -> (CachedProvider, Requests) {
+        cached(name, false)
+    }
+
+    fn cached(name: &str, weekdays: bool) -> (CachedProvider, Requests) {
+        let directory = temp_path(name);
+        let requests = Rc::new(RefCell::new(Vec::new()));
+        let provider = CachedProvider::new(
+            &directory,
+            Box::new(DailyProvider {
+                requests: Rc::clone(&requests),
+                weekdays,
+            }),
+        );
+        (provider, requests)
+    }
+
+    #[test]
+    fn only_fetches_days_not_cached() {
+        let (provider, requests) = provider("cache-range");
+
+        let first =
+            tokio_test::block_on(provider.get_bars("msft", date("2020-01-01"), date("2020-01-31")))
+                .unwrap();
+        let second =
+            tokio_test::block_on(provider.get_bars("MSFT", date("2020-01-10"), date("2020-01-19")))
+                .unwrap();
+        let third =
+            tokio_test::block_on(provider.get_bars("MSFT", date("2019-12-22"), date("2020-02-09")))
+                .unwrap();
+
+        assert_eq!(
+            vec![
+                (date("2020-01-01"), date("2020-01-31")),
+                (date("2019-12-22"), date("2019-12-31")),
+                (date("2020-02-01"), date("2020-02-09")),
+            ],
+            *requests.borrow()
+        );
+        assert_eq!(31, first.len());
+        assert_eq!(10, second.len());
+        assert_eq!(50, third.len());
+        assert!(provider.path("MSFT", Interval::Day).is_file());
+        fs::remove_dir_all(&provider.directory).unwrap();
+    }
+
+    #[test]
+    fn extends_over_days_without_bars() {
+        let (provider, requests) = cached("cache-weekend", true);
+
+        // from a Wednesday to a Friday, then on until the Sunday after
+        tokio_test::block_on(provider.get_bars("MSFT", date("2020-01-01"), date("2020-01-03")))
+            .unwrap();
+        let bars =
+            tokio_test::block_on(provider.get_bars("MSFT", date("2020-01-01"), date("2020-01-05")))
+                .unwrap();
+        tokio_test::block_on(provider.get_bars("MSFT", date("2020-01-01"), date("2020-01-05")))
+            .unwrap();
+
+        assert_eq!(3, bars.len());
+        assert_eq!(
+            vec![
+                (date("2020-01-01"), date("2020-01-03")),
+                (date("2020-01-04"), date("2020-01-05")),
+            ],
+            *requests.borrow()
+        );
+        fs::remove_dir_all(&provider.directory).unwrap();
+    }
+
+    #[test]
+    fn fetches_today_again() {
+        let (provider, requests) = provider("cache-today");
+        let today = Utc::now().date_naive();
+        let start = today - Duration::days(5);
+
+        tokio_test::block_on(provider.get_bars("IBM", start, today)).unwrap();
+        let bars = tokio_test::block_on(provider.get_bars("IBM", start, today)).unwrap();
+
+        assert_eq!(vec![(start, today), (today, today)], *requests.borrow());
+        assert_eq!(6, bars.len());
+        fs::remove_dir_all(&provider.directory).unwrap();
+    }
+}
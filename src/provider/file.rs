@@ -0,0 +1,375 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use super::{day_start, within, Bar, Interval, PriceProvider, PriceSeries};
+use crate::{csv, SstraError};
+
+/// Reads historical bars from local CSV or JSON files.
+///
+/// `path` is either a single file holding bars for several symbols, in which
+/// case every record needs a `symbol` field, or a directory containing one
+/// `<SYMBOL>.csv` or `<SYMBOL>.json` file per symbol.
+///
+/// CSV files need a header row naming the columns; `date` (YYYY-MM-DD) or
+/// `timestamp` (seconds since the Unix epoch), `open`, `high`, `low`, `close`,
+/// `adj close`, `volume`, `currency` and `utc offset` (the exchange's offset
+/// from UTC in seconds east, 0 by default) are recognized, in any order. JSON
+/// files hold an array of objects with the same fields. If no adjusted close
+/// is given, the close is used instead. Dates are the exchange's local dates.
+///
+/// Intraday bars are read from a directory's `<SYMBOL>-<interval>.csv` or
+/// `.json` files, e.g. `MSFT-5m.csv`, whose records need a `timestamp`.
+pub struct FileProvider {
+    path: PathBuf,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Record {
+    #[serde(default)]
+    symbol: Option<String>,
+    #[serde(default)]
+    date: Option<String>,
+    #[serde(default)]
+    timestamp: Option<i64>,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    #[serde(default, alias = "adj_close", alias = "adjClose")]
+    adjclose: Option<f64>,
+    #[serde(default)]
+    volume: u64,
+    #[serde(default)]
+    currency: Option<String>,
+    #[serde(default, alias = "utcOffset")]
+    utc_offset: i32,
+}
+
+impl FileProvider {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileProvider { path: path.into() }
+    }
+
+    fn load(&self, symbol: &str, interval: Interval) -> Result<Vec<Record>, SstraError> {
+        let name = match interval {
+            Interval::Day => symbol.to_string(),
+            interval => format!("{}-{}", symbol, interval),
+        };
+        if self.path.is_dir() {
+            for extension in &["csv", "json"] {
+                let file = self.path.join(format!("{}.{}", name, extension));
+                if file.is_file() {
+                    return read_records(&file);
+                }
+            }
+            return Err(SstraError::UnknownSymbol(symbol.to_string()));
+        }
+        if !interval.is_day() {
+            return Err(SstraError::Provider(format!(
+                "No {} bars available for {} in {}",
+                interval,
+                symbol,
+                self.path.display()
+            )));
+        }
+        let records: Vec<Record> = read_records(&self.path)?
+            .into_iter()
+            .filter(|record| match &record.symbol {
+                Some(s) => s.eq_ignore_ascii_case(symbol),
+                None => false,
+            })
+            .collect();
+        if records.is_empty() {
+            return Err(SstraError::UnknownSymbol(symbol.to_string()));
+        }
+        Ok(records)
+    }
+}
+
+#[async_trait(?Send)]
+impl PriceProvider for FileProvider {
+    async fn get_interval_series(
+        &self,
+        symbol: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+        interval: Interval,
+    ) -> Result<PriceSeries, SstraError> {
+        let records = self.load(symbol, interval)?;
+        let currency = records.iter().find_map(|record| record.currency.clone());
+        let mut bars = Vec::new();
+        for record in records {
+            let bar = Bar {
+                timestamp: record_timestamp(&record)?,
+                open: record.open,
+                high: record.high,
+                low: record.low,
+                close: record.close,
+                adjclose: record.adjclose.unwrap_or(record.close),
+                volume: record.volume,
+                utc_offset: record.utc_offset,
+            };
+            if within(&bar, start, end) {
+                bars.push(bar);
+            }
+        }
+        bars.sort_by_key(|bar| bar.timestamp);
+        // the files only have the offsets of the bars, not their timezones
+        Ok(PriceSeries {
+            currency,
+            timezone: None,
+            bars,
+        })
+    }
+}
+
+fn read_records(path: &Path) -> Result<Vec<Record>, SstraError> {
+    let contents = fs::read_to_string(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(e) if e.eq_ignore_ascii_case("json") => serde_json::from_str(&contents)
+            .map_err(|err| invalid_data(format!("{}: {}", path.display(), err))),
+        _ => {
+            parse_csv(&contents).map_err(|err| invalid_data(format!("{}: {}", path.display(), err)))
+        }
+    }
+}
+
+/// Reads price records from a CSV file.
+fn parse_csv(contents: &str) -> Result<Vec<Record>, String> {
+    csv::parse(contents, |row, fields| {
+        let mut record = Record::default();
+        for &(column, value) in fields {
+            let invalid = || csv::invalid(row, column, value);
+            match column {
+                "symbol" => record.symbol = Some(value.to_string()),
+                "date" => record.date = Some(value.to_string()),
+                "timestamp" => record.timestamp = Some(value.parse().map_err(|_| invalid())?),
+                "open" => record.open = value.parse().map_err(|_| invalid())?,
+                "high" => record.high = value.parse().map_err(|_| invalid())?,
+                "low" => record.low = value.parse().map_err(|_| invalid())?,
+                "close" => record.close = value.parse().map_err(|_| invalid())?,
+                "adjclose" => record.adjclose = Some(value.parse().map_err(|_| invalid())?),
+                "volume" => record.volume = value.parse().map_err(|_| invalid())?,
+                "currency" => record.currency = Some(value.to_string()),
+                "utcoffset" => record.utc_offset = value.parse().map_err(|_| invalid())?,
+                _ => {}
+            }
+        }
+        Ok(record)
+    })
+}
+
+/// The record's timestamp, or the start of its local date.
+fn record_timestamp(record: &Record) -> Result<i64, SstraError> {
+    if let Some(timestamp) = record.timestamp {
+        return Ok(timestamp);
+    }
+    match &record.date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(|date| day_start(date) - record.utc_offset as i64)
+            .map_err(|err| SstraError::InvalidDate(format!("{}: {}", date, err))),
+        None => Err(invalid_data(String::from(
+            "Price record has neither a date nor a timestamp",
+        ))),
+    }
+}
+
+fn invalid_data(message: String) -> SstraError {
+    SstraError::Provider(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{date, temp_path};
+
+    fn all_bars(provider: &FileProvider, symbol: &str) -> Result<Vec<Bar>, SstraError> {
+        tokio_test::block_on(provider.get_bars(symbol, date("1970-01-01"), date("2100-01-01")))
+    }
+
+    #[test]
+    fn reads_per_symbol_csv_files() {
+        let dir = temp_path("csv-dir");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("MSFT.csv"),
+            "Date,Open,High,Low,Close,Adj Close,Volume\n\
+             2020-06-02,1,3,1,2,2.5,100\n\
+             2020-06-01,1,2,1,1,1.5,100\n",
+        )
+        .unwrap();
+
+        let provider = FileProvider::new(&dir);
+        let bars = all_bars(&provider, "MSFT").unwrap();
+        assert_eq!(
+            vec![1.5, 2.5],
+            bars.iter().map(|b| b.adjclose).collect::<Vec<f64>>()
+        );
+        assert!(matches!(
+            all_bars(&provider, "AAPL"),
+            Err(SstraError::UnknownSymbol(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_local_dates() {
+        let dir = temp_path("csv-offset");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("7203.T.csv"),
+            "Date,Close,UTC Offset\n2020-06-01,1,32400\n2020-06-02,2,32400\n",
+        )
+        .unwrap();
+
+        let provider = FileProvider::new(&dir);
+        let bars = tokio_test::block_on(provider.get_bars(
+            "7203.T",
+            date("2020-06-02"),
+            date("2020-06-02"),
+        ))
+        .unwrap();
+        assert_eq!(
+            vec![2.0],
+            bars.iter().map(|b| b.close).collect::<Vec<f64>>()
+        );
+        // midnight in Tokyo is still the day before in UTC
+        assert_eq!(day_start(date("2020-06-02")) - 32400, bars[0].timestamp);
+        assert_eq!(date("2020-06-02"), bars[0].date());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_intraday_files() {
+        let dir = temp_path("csv-intraday");
+        fs::create_dir_all(&dir).unwrap();
+        // 09:30 and 09:35 in New York on 2020-06-01 and 09:30 the day after
+        fs::write(
+            dir.join("MSFT-5m.csv"),
+            "Timestamp,Close,UTC Offset\n\
+             1591018200,1,-14400\n\
+             1591018500,2,-14400\n\
+             1591104600,3,-14400\n",
+        )
+        .unwrap();
+        fs::write(dir.join("MSFT.csv"), "Date,Close\n2020-06-01,4\n").unwrap();
+
+        let provider = FileProvider::new(&dir);
+        let series = tokio_test::block_on(provider.get_interval_series(
+            "MSFT",
+            date("2020-06-01"),
+            date("2020-06-01"),
+            Interval::FiveMinutes,
+        ))
+        .unwrap();
+        assert_eq!(
+            vec![1.0, 2.0],
+            series.bars.iter().map(|b| b.close).collect::<Vec<f64>>()
+        );
+        assert_eq!(
+            vec![4.0],
+            all_bars(&provider, "MSFT")
+                .unwrap()
+                .iter()
+                .map(|b| b.close)
+                .collect::<Vec<f64>>()
+        );
+        assert!(matches!(
+            tokio_test::block_on(provider.get_interval_series(
+                "MSFT",
+                date("2020-06-01"),
+                date("2020-06-01"),
+                Interval::OneHour,
+            )),
+            Err(SstraError::UnknownSymbol(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_combined_json_file() {
+        let dir = temp_path("json-file");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("prices.json");
+        fs::write(
+            &file,
+            r#"[
+                {"symbol": "IBM", "timestamp": 1590969600, "open": 1, "high": 1, "low": 1, "close": 7, "volume": 5},
+                {"symbol": "UBER", "date": "2020-06-01", "open": 1, "high": 1, "low": 1, "close": 3, "adjclose": 4}
+            ]"#,
+        )
+        .unwrap();
+
+        let provider = FileProvider::new(&file);
+        let bars = all_bars(&provider, "ibm").unwrap();
+        assert_eq!(
+            vec![Bar {
+                timestamp: 1590969600,
+                open: 1.0,
+                high: 1.0,
+                low: 1.0,
+                close: 7.0,
+                adjclose: 7.0,
+                volume: 5,
+                utc_offset: 0,
+            }],
+            bars
+        );
+        let bars = all_bars(&provider, "UBER").unwrap();
+        assert_eq!(4.0, bars[0].adjclose);
+        let series = tokio_test::block_on(provider.get_series(
+            "UBER",
+            date("2020-01-01"),
+            date("2020-12-31"),
+        ))
+        .unwrap();
+        assert_eq!(None, series.currency);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_currency() {
+        let dir = temp_path("currency");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("SAP.DE.csv"),
+            "date,close,currency\n2020-06-01,1,EUR\n",
+        )
+        .unwrap();
+
+        let provider = FileProvider::new(&dir);
+        let series = tokio_test::block_on(provider.get_series(
+            "SAP.DE",
+            date("2020-01-01"),
+            date("2020-12-31"),
+        ))
+        .unwrap();
+        assert_eq!(Some(String::from("EUR")), series.currency);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_returns_bars_within_range() {
+        let dir = temp_path("range");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("GOOG.csv"),
+            "date,close\n2020-01-02,1\n2020-01-03,2\n2020-01-06,3\n2020-01-07,4\n",
+        )
+        .unwrap();
+
+        let provider = FileProvider::new(&dir);
+        let bars =
+            tokio_test::block_on(provider.get_bars("GOOG", date("2020-01-03"), date("2020-01-06")))
+                .unwrap();
+        assert_eq!(
+            vec![2.0, 3.0],
+            bars.iter().map(|b| b.adjclose).collect::<Vec<f64>>()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
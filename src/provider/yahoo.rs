@@ -0,0 +1,106 @@
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate, Offset, TimeZone};
+use chrono_tz::Tz;
+use time::OffsetDateTime;
+use yahoo_finance_api as yahoo;
+
+use super::{day_start, within, Bar, Interval, PriceProvider, PriceSeries};
+use crate::SstraError;
+
+/// Fetches daily or intraday bars from the Yahoo! Finance API.
+pub struct YahooProvider {
+    connector: yahoo::YahooConnector,
+}
+
+impl YahooProvider {
+    pub fn new() -> Self {
+        YahooProvider {
+            connector: yahoo::YahooConnector::new(),
+        }
+    }
+}
+
+impl Default for YahooProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait(?Send)]
+impl PriceProvider for YahooProvider {
+    async fn get_interval_series(
+        &self,
+        symbol: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+        interval: Interval,
+    ) -> Result<PriceSeries, SstraError> {
+        // the local dates of exchanges far from UTC start on the UTC day
+        // before or end on the one after, which only matters to daily bars
+        // since they're timestamped at midnight
+        let margin = Duration::days(if interval.is_day() { 1 } else { 0 });
+        let response = self
+            .connector
+            .get_quote_history_interval(
+                symbol,
+                to_offset_date_time(start - margin),
+                to_offset_date_time(end + Duration::days(1) + margin),
+                interval.as_str(),
+            )
+            .await
+            .map_err(|err| to_sstra_error(symbol, err))?;
+        let quotes = response
+            .quotes()
+            .map_err(|err| to_sstra_error(symbol, err))?;
+        let meta = response.chart.result.first().map(|result| &result.meta);
+        let currency = meta.map(|meta| meta.currency.clone());
+        // the offset Yahoo reports is today's, so follow the timezone's
+        // daylight saving rules where it's known
+        let timezone: Option<Tz> = meta.and_then(|meta| meta.exchange_timezone_name.parse().ok());
+        let gmtoffset = meta.map(|meta| meta.gmtoffset).unwrap_or(0);
+        let bars = quotes
+            .iter()
+            .map(|quote| Bar {
+                timestamp: quote.timestamp as i64,
+                open: quote.open,
+                high: quote.high,
+                low: quote.low,
+                close: quote.close,
+                adjclose: quote.adjclose,
+                volume: quote.volume,
+                utc_offset: match timezone {
+                    Some(timezone) => timezone
+                        .timestamp_opt(quote.timestamp as i64, 0)
+                        .single()
+                        .map_or(gmtoffset, |instant| {
+                            instant.offset().fix().local_minus_utc()
+                        }),
+                    None => gmtoffset,
+                },
+            })
+            .filter(|bar| within(bar, start, end))
+            .collect();
+        Ok(PriceSeries {
+            currency,
+            timezone,
+            bars,
+        })
+    }
+}
+
+fn to_offset_date_time(date: NaiveDate) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(day_start(date)).unwrap()
+}
+
+fn to_sstra_error(symbol: &str, err: yahoo::YahooError) -> SstraError {
+    match err {
+        yahoo::YahooError::FetchFailed(status) if status.starts_with("404") => {
+            SstraError::UnknownSymbol(symbol.to_string())
+        }
+        yahoo::YahooError::EmptyDataSet => SstraError::EmptySeries(symbol.to_string()),
+        err => SstraError::Provider(format!(
+            "Encountered a problem calling the Yahoo! Finance API for {}: {:?}",
+            symbol, err
+        )),
+    }
+}
@@ -0,0 +1,641 @@
+//! Risk statistics calculated from a series of prices.
+//!
+//! Ratios are annualized assuming `TRADING_DAYS_PER_YEAR` prices a year, and
+//! rates and returns are fractions rather than percentages, e.g. 0.05 for 5%.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::Bar;
+
+/// The number of daily prices in a year, used to annualize daily statistics.
+pub const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+/// The parameters of the risk statistics.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RiskParameters {
+    /// The annual return of a risk-free investment, e.g. 0.04.
+    pub risk_free_rate: f64,
+}
+
+/// The risk statistics reported in `StockInfo`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RiskStatistics {
+    /// The annualized standard deviation of the daily log returns.
+    pub volatility: f64,
+    /// `None` if the returns don't vary.
+    pub sharpe_ratio: Option<f64>,
+    /// `None` if no return fell short of the risk-free rate.
+    pub sortino_ratio: Option<f64>,
+    /// `None` if the price never fell below an earlier one.
+    pub max_drawdown: Option<Drawdown>,
+}
+
+/// The largest fall from a peak to a later trough.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Drawdown {
+    /// The fall as a fraction of the peak, e.g. 0.25 for a 25% drawdown.
+    pub depth: f64,
+    pub peak: NaiveDate,
+    pub trough: NaiveDate,
+    /// The first day the price was back at the peak, if it recovered.
+    pub recovery: Option<NaiveDate>,
+    /// The number of days from the peak until the recovery, or until the last
+    /// price if it hasn't recovered yet.
+    pub duration_days: i64,
+}
+
+/// How a symbol performed relative to a benchmark.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BenchmarkStatistics {
+    /// The benchmark's symbol.
+    pub symbol: String,
+    /// `None` if the benchmark's returns don't vary.
+    pub beta: Option<f64>,
+    /// Jensen's alpha, annualized.
+    pub alpha: Option<f64>,
+    /// The correlation of the daily returns, or `None` if either of them
+    /// doesn't vary.
+    pub correlation: Option<f64>,
+    /// The annualized standard deviation of the differences between the
+    /// daily returns.
+    pub tracking_error: f64,
+    /// The annualized mean difference between the daily returns per unit of
+    /// tracking error.
+    pub information_ratio: Option<f64>,
+}
+
+/// The correlations, and optionally covariances, of several symbols' daily
+/// returns, in the order the symbols were given.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CorrelationMatrix {
+    pub symbols: Vec<String>,
+    /// The number of days every symbol had a price on.
+    pub days: usize,
+    /// `None` where either symbol's returns don't vary.
+    pub correlation: Vec<Vec<Option<f64>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub covariance: Option<Vec<Vec<f64>>>,
+}
+
+/// Calculates all risk statistics of a series of bars, or `None` if there are
+/// fewer than three prices, i.e. two returns.
+pub async fn risk_statistics(parameters: RiskParameters, bars: &[Bar]) -> Option<RiskStatistics> {
+    let prices = crate::closing_prices(bars);
+    if prices.len() < 3 {
+        return None;
+    }
+    let returns = simple_returns(&prices).await;
+    Some(RiskStatistics {
+        volatility: annualized_volatility(&log_returns(&prices).await).await?,
+        sharpe_ratio: sharpe_ratio(&returns, parameters.risk_free_rate).await,
+        sortino_ratio: sortino_ratio(&returns, parameters.risk_free_rate).await,
+        max_drawdown: max_drawdown(bars).await,
+    })
+}
+
+/// Calculates how `bars` performed relative to `benchmark`, comparing the
+/// returns between the days both of them have prices for.
+///
+/// Returns `None` if there are fewer than three such days.
+pub async fn benchmark_statistics(
+    parameters: RiskParameters,
+    bars: &[Bar],
+    benchmark: &str,
+    benchmark_bars: &[Bar],
+) -> Option<BenchmarkStatistics> {
+    let (prices, benchmark_prices) = align(bars, benchmark_bars).await;
+    if prices.len() < 3 {
+        return None;
+    }
+    let returns = excess_returns(&simple_returns(&prices).await, parameters.risk_free_rate);
+    let benchmark_returns = excess_returns(
+        &simple_returns(&benchmark_prices).await,
+        parameters.risk_free_rate,
+    );
+
+    let covariance = sample_covariance(&returns, &benchmark_returns)?;
+    let deviation = sample_std_dev(&returns)?;
+    let benchmark_deviation = sample_std_dev(&benchmark_returns)?;
+    let beta = if benchmark_deviation > 0.0 {
+        Some(covariance / benchmark_deviation.powi(2))
+    } else {
+        None
+    };
+    let alpha = match beta {
+        Some(beta) => {
+            Some((mean(&returns)? - beta * mean(&benchmark_returns)?) * TRADING_DAYS_PER_YEAR)
+        }
+        None => None,
+    };
+    let correlation = if deviation > 0.0 && benchmark_deviation > 0.0 {
+        Some(covariance / (deviation * benchmark_deviation))
+    } else {
+        None
+    };
+
+    let active: Vec<f64> = returns
+        .iter()
+        .zip(&benchmark_returns)
+        .map(|(value, benchmark)| value - benchmark)
+        .collect();
+    let tracking_error = annualized_volatility(&active).await?;
+    let information_ratio = if tracking_error > 0.0 {
+        Some(mean(&active)? * TRADING_DAYS_PER_YEAR / tracking_error)
+    } else {
+        None
+    };
+
+    Some(BenchmarkStatistics {
+        symbol: benchmark.to_string(),
+        beta,
+        alpha,
+        correlation,
+        tracking_error,
+        information_ratio,
+    })
+}
+
+/// Calculates the correlation matrix of the daily returns of several
+/// symbols, given as (symbol, bars), over the days all of them have prices
+/// for.
+///
+/// Returns `None` if there are fewer than three such days.
+pub async fn correlation_matrix(
+    series: &[(String, Vec<Bar>)],
+    with_covariance: bool,
+) -> Option<CorrelationMatrix> {
+    let bars: Vec<&[Bar]> = series.iter().map(|(_, bars)| bars.as_slice()).collect();
+    let aligned = align_all(&bars).await;
+    let days = aligned.first()?.len();
+    if days < 3 {
+        return None;
+    }
+    let mut returns = Vec::new();
+    for prices in &aligned {
+        returns.push(simple_returns(prices).await);
+    }
+    let deviations: Vec<f64> = returns
+        .iter()
+        .map(|r| sample_std_dev(r))
+        .collect::<Option<_>>()?;
+    let mut covariance = Vec::new();
+    let mut correlation = Vec::new();
+    for (first, first_deviation) in returns.iter().zip(&deviations) {
+        let mut covariance_row = Vec::new();
+        let mut correlation_row = Vec::new();
+        for (second, second_deviation) in returns.iter().zip(&deviations) {
+            let value = sample_covariance(first, second)?;
+            covariance_row.push(value);
+            correlation_row.push(if *first_deviation > 0.0 && *second_deviation > 0.0 {
+                Some(value / (first_deviation * second_deviation))
+            } else {
+                None
+            });
+        }
+        covariance.push(covariance_row);
+        correlation.push(correlation_row);
+    }
+    Some(CorrelationMatrix {
+        symbols: series.iter().map(|(symbol, _)| symbol.clone()).collect(),
+        days,
+        correlation,
+        covariance: if with_covariance {
+            Some(covariance)
+        } else {
+            None
+        },
+    })
+}
+
+/// Pairs up the adjusted closes of two series on the days both have a bar
+/// for, in the order of `bars`.
+pub async fn align(bars: &[Bar], other: &[Bar]) -> (Vec<f64>, Vec<f64>) {
+    let mut aligned = align_all(&[bars, other]).await;
+    let other = aligned.pop().unwrap_or_default();
+    let bars = aligned.pop().unwrap_or_default();
+    (bars, other)
+}
+
+/// Lines up the adjusted closes of several series on the days all of them
+/// have a bar for, in the order of the first series.
+pub async fn align_all(series: &[&[Bar]]) -> Vec<Vec<f64>> {
+    let (first, rest) = match series.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+    let others: Vec<HashMap<NaiveDate, f64>> = rest
+        .iter()
+        .map(|bars| bars.iter().map(|bar| (bar.date(), bar.adjclose)).collect())
+        .collect();
+    let mut aligned = vec![Vec::new(); series.len()];
+    for bar in first.iter() {
+        let date = bar.date();
+        let prices: Option<Vec<f64>> = others.iter().map(|o| o.get(&date).copied()).collect();
+        if let Some(prices) = prices {
+            aligned[0].push(bar.adjclose);
+            for (column, price) in aligned[1..].iter_mut().zip(prices) {
+                column.push(price);
+            }
+        }
+    }
+    aligned
+}
+
+/// The relative change from each price to the next.
+pub async fn simple_returns(series: &[f64]) -> Vec<f64> {
+    series
+        .windows(2)
+        .map(|pair| pair[1] / pair[0] - 1.0)
+        .collect()
+}
+
+/// The natural logarithm of the ratio of each price to the previous one.
+pub async fn log_returns(series: &[f64]) -> Vec<f64> {
+    series
+        .windows(2)
+        .map(|pair| (pair[1] / pair[0]).ln())
+        .collect()
+}
+
+/// The annualized sample standard deviation of daily returns.
+pub async fn annualized_volatility(returns: &[f64]) -> Option<f64> {
+    Some(sample_std_dev(returns)? * TRADING_DAYS_PER_YEAR.sqrt())
+}
+
+/// The annualized mean return in excess of `risk_free_rate` per unit of
+/// volatility.
+pub async fn sharpe_ratio(returns: &[f64], risk_free_rate: f64) -> Option<f64> {
+    let excess = excess_returns(returns, risk_free_rate);
+    let deviation = sample_std_dev(&excess)?;
+    if deviation == 0.0 {
+        return None;
+    }
+    Some(mean(&excess)? / deviation * TRADING_DAYS_PER_YEAR.sqrt())
+}
+
+/// Like the Sharpe ratio, but only counts returns below the risk-free rate
+/// as risk.
+pub async fn sortino_ratio(returns: &[f64], risk_free_rate: f64) -> Option<f64> {
+    let excess = excess_returns(returns, risk_free_rate);
+    let average = mean(&excess)?;
+    let downside = excess
+        .iter()
+        .map(|value| value.min(0.0).powi(2))
+        .sum::<f64>()
+        / excess.len() as f64;
+    if downside == 0.0 {
+        return None;
+    }
+    Some(average / downside.sqrt() * TRADING_DAYS_PER_YEAR.sqrt())
+}
+
+/// Finds the largest fall in the adjusted close from a peak to a later
+/// trough.
+pub async fn max_drawdown(bars: &[Bar]) -> Option<Drawdown> {
+    let series: Vec<(NaiveDate, f64)> = bars.iter().map(|bar| (bar.date(), bar.adjclose)).collect();
+    dated_max_drawdown(&series).await
+}
+
+/// Finds the largest fall in a series of (date, value) from a peak to a later
+/// trough.
+pub async fn dated_max_drawdown(series: &[(NaiveDate, f64)]) -> Option<Drawdown> {
+    let mut peak = series.first()?;
+    // (depth, peak, trough)
+    let mut deepest: Option<(f64, &(NaiveDate, f64), usize)> = None;
+    for (i, point) in series.iter().enumerate() {
+        if point.1 > peak.1 {
+            peak = point;
+            continue;
+        }
+        let depth = 1.0 - point.1 / peak.1;
+        if depth > deepest.map_or(0.0, |(depth, _, _)| depth) {
+            deepest = Some((depth, peak, i));
+        }
+    }
+    let (depth, &(peak, peak_value), trough) = deepest?;
+    let recovery = series[trough + 1..]
+        .iter()
+        .find(|(_, value)| *value >= peak_value)
+        .map(|(date, _)| *date);
+    let end = recovery.unwrap_or(series[series.len() - 1].0);
+    Some(Drawdown {
+        depth,
+        peak,
+        trough: series[trough].0,
+        recovery,
+        duration_days: (end - peak).num_days(),
+    })
+}
+
+/// Subtracts the daily share of an annual rate from each return.
+fn excess_returns(returns: &[f64], risk_free_rate: f64) -> Vec<f64> {
+    let daily_rate = risk_free_rate / TRADING_DAYS_PER_YEAR;
+    returns.iter().map(|value| value - daily_rate).collect()
+}
+
+fn mean(series: &[f64]) -> Option<f64> {
+    if series.is_empty() {
+        return None;
+    }
+    Some(series.iter().sum::<f64>() / series.len() as f64)
+}
+
+impl fmt::Display for CorrelationMatrix {
+    /// Writes the matrices as CSV, the covariance one after a blank line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_matrix(f, "correlation", &self.symbols, &self.correlation, 6)?;
+        if let Some(covariance) = &self.covariance {
+            let covariance: Vec<Vec<Option<f64>>> = covariance
+                .iter()
+                .map(|row| row.iter().copied().map(Some).collect())
+                .collect();
+            writeln!(f)?;
+            // daily covariances are tiny, so they need more decimals
+            write_matrix(f, "covariance", &self.symbols, &covariance, 10)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_matrix(
+    f: &mut fmt::Formatter,
+    name: &str,
+    symbols: &[String],
+    rows: &[Vec<Option<f64>>],
+    decimals: usize,
+) -> fmt::Result {
+    writeln!(f, "{},{}", name, symbols.join(","))?;
+    for (symbol, row) in symbols.iter().zip(rows) {
+        write!(f, "{}", symbol)?;
+        for value in row {
+            match value {
+                Some(value) => write!(f, ",{:.*}", decimals, value)?,
+                None => write!(f, ",")?,
+            }
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+fn sample_covariance(first: &[f64], second: &[f64]) -> Option<f64> {
+    if first.len() < 2 || first.len() != second.len() {
+        return None;
+    }
+    let (first_avg, second_avg) = (mean(first)?, mean(second)?);
+    let sum = first
+        .iter()
+        .zip(second)
+        .map(|(a, b)| (a - first_avg) * (b - second_avg))
+        .sum::<f64>();
+    Some(sum / (first.len() - 1) as f64)
+}
+
+fn sample_std_dev(series: &[f64]) -> Option<f64> {
+    if series.len() < 2 {
+        return None;
+    }
+    let avg = mean(series)?;
+    let variance = series
+        .iter()
+        .map(|value| (value - avg).powi(2))
+        .sum::<f64>()
+        / (series.len() - 1) as f64;
+    Some(variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::day_start;
+    use crate::testing::date;
+    use chrono::Duration;
+
+    /// One bar per day, starting on 2020-01-01.
+    fn bars(prices: &[f64]) -> Vec<Bar> {
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, &adjclose)| Bar {
+                timestamp: day_start(date("2020-01-01") + Duration::days(i as i64)),
+                adjclose,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn calculates_returns() {
+        let x = [100.0, 110.0, 99.0];
+        assert_eq!(
+            vec![0.1, -0.1],
+            tokio_test::block_on(simple_returns(&x))
+                .iter()
+                .map(|r| (r * 1e9).round() / 1e9)
+                .collect::<Vec<f64>>()
+        );
+        assert_eq!(
+            vec![1.1f64.ln(), 0.9f64.ln()],
+            tokio_test::block_on(log_returns(&x))
+        );
+    }
+
+    #[test]
+    fn calculates_volatility() {
+        let returns = [0.01, -0.01, 0.01, -0.01];
+        let expected = (0.0004f64 / 3.0).sqrt() * TRADING_DAYS_PER_YEAR.sqrt();
+        assert_eq!(
+            Some(expected),
+            tokio_test::block_on(annualized_volatility(&returns))
+        );
+        assert_eq!(None, tokio_test::block_on(annualized_volatility(&[0.01])));
+    }
+
+    #[test]
+    fn needs_three_prices_for_risk_statistics() {
+        let parameters = RiskParameters::default();
+        assert_eq!(
+            None,
+            tokio_test::block_on(risk_statistics(parameters, &bars(&[100.0, 110.0])))
+        );
+        assert!(
+            tokio_test::block_on(risk_statistics(parameters, &bars(&[100.0, 110.0, 99.0])))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn calculates_sharpe_and_sortino_ratios() {
+        let returns = [0.02, -0.01, 0.02, -0.01];
+        let daily = 0.005 / (0.0009f64 / 3.0).sqrt();
+        let sharpe = tokio_test::block_on(sharpe_ratio(&returns, 0.0)).unwrap();
+        assert!((sharpe - daily * TRADING_DAYS_PER_YEAR.sqrt()).abs() < 1e-9);
+        // the downside deviation only counts the two losing days
+        let sortino = tokio_test::block_on(sortino_ratio(&returns, 0.0)).unwrap();
+        let downside = (0.0002f64 / 4.0).sqrt();
+        assert!((sortino - 0.005 / downside * TRADING_DAYS_PER_YEAR.sqrt()).abs() < 1e-9);
+
+        assert_eq!(
+            None,
+            tokio_test::block_on(sortino_ratio(&[0.01, 0.02], 0.0))
+        );
+        assert_eq!(None, tokio_test::block_on(sharpe_ratio(&[0.01, 0.01], 0.0)));
+        // a rate above every return makes both ratios negative
+        assert!(tokio_test::block_on(sortino_ratio(&returns, 10.0)).unwrap() < 0.0);
+    }
+
+    #[test]
+    fn aligns_series_by_date() {
+        let mut other = bars(&[10.0, 20.0, 30.0, 40.0]);
+        other.remove(1);
+        let (prices, other_prices) = tokio_test::block_on(align(&bars(&[1.0, 2.0, 3.0]), &other));
+        assert_eq!(vec![1.0, 3.0], prices);
+        assert_eq!(vec![10.0, 30.0], other_prices);
+    }
+
+    #[test]
+    fn aligns_series_by_local_date() {
+        // Tokyo's bars start at midnight local time, the day before in UTC,
+        // and New York's at the open
+        let tokyo: Vec<Bar> = bars(&[1.0, 2.0, 3.0])
+            .into_iter()
+            .map(|bar| Bar {
+                timestamp: bar.timestamp - 9 * 3600,
+                utc_offset: 9 * 3600,
+                ..bar
+            })
+            .collect();
+        let new_york: Vec<Bar> = bars(&[10.0, 20.0, 30.0])
+            .into_iter()
+            .map(|bar| Bar {
+                timestamp: bar.timestamp + 14 * 3600 + 1800,
+                utc_offset: -5 * 3600,
+                ..bar
+            })
+            .collect();
+        let (prices, other_prices) = tokio_test::block_on(align(&tokyo, &new_york));
+        assert_eq!(vec![1.0, 2.0, 3.0], prices);
+        assert_eq!(vec![10.0, 20.0, 30.0], other_prices);
+    }
+
+    #[test]
+    fn compares_with_benchmark() {
+        let benchmark = bars(&[100.0, 101.0, 99.0, 102.0, 100.0]);
+        // twice the benchmark's returns, plus 0.1% a day
+        let returns = tokio_test::block_on(simple_returns(&crate::closing_prices(&benchmark)));
+        let mut prices = vec![50.0];
+        for r in &returns {
+            prices.push(prices.last().unwrap() * (1.0 + 2.0 * r + 0.001));
+        }
+        let statistics = tokio_test::block_on(benchmark_statistics(
+            RiskParameters::default(),
+            &bars(&prices),
+            "SPY",
+            &benchmark,
+        ))
+        .unwrap();
+        assert_eq!("SPY", statistics.symbol);
+        assert!((statistics.beta.unwrap() - 2.0).abs() < 1e-9);
+        assert!((statistics.alpha.unwrap() - 0.001 * TRADING_DAYS_PER_YEAR).abs() < 1e-9);
+        assert!((statistics.correlation.unwrap() - 1.0).abs() < 1e-9);
+        // the active returns are the benchmark's returns plus 0.1%
+        let tracking_error = tokio_test::block_on(annualized_volatility(&returns)).unwrap();
+        assert!((statistics.tracking_error - tracking_error).abs() < 1e-9);
+
+        let flat = bars(&[100.0, 100.0, 100.0]);
+        let statistics = tokio_test::block_on(benchmark_statistics(
+            RiskParameters::default(),
+            &bars(&[1.0, 2.0, 1.0]),
+            "CASH",
+            &flat,
+        ))
+        .unwrap();
+        assert_eq!(
+            (None, None, None),
+            (statistics.beta, statistics.alpha, statistics.correlation)
+        );
+        assert_eq!(
+            None,
+            tokio_test::block_on(benchmark_statistics(
+                RiskParameters::default(),
+                &bars(&[1.0, 2.0]),
+                "SPY",
+                &benchmark,
+            ))
+        );
+    }
+
+    #[test]
+    fn calculates_correlation_matrix() {
+        let series = vec![
+            (String::from("A"), bars(&[100.0, 110.0, 99.0, 108.9])),
+            (String::from("B"), bars(&[50.0, 45.0, 49.5, 44.55, 1.0])),
+            (String::from("C"), bars(&[10.0, 10.0, 10.0, 10.0])),
+        ];
+        let matrix = tokio_test::block_on(correlation_matrix(&series, true)).unwrap();
+        assert_eq!(4, matrix.days);
+        let rounded: Vec<Vec<Option<f64>>> = matrix
+            .correlation
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|v| v.map(|v| (v * 1e9).round() / 1e9))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                vec![Some(1.0), Some(-1.0), None],
+                vec![Some(-1.0), Some(1.0), None],
+                vec![None, None, None],
+            ],
+            rounded
+        );
+        assert_eq!(0.0, matrix.covariance.as_ref().unwrap()[2][0]);
+        assert!(matrix
+            .to_string()
+            .starts_with("correlation,A,B,C\nA,1.000000,-1.000000,\n"));
+        assert_eq!(
+            None,
+            tokio_test::block_on(correlation_matrix(&series[..2], false))
+                .unwrap()
+                .covariance
+        );
+        assert_eq!(
+            None,
+            tokio_test::block_on(correlation_matrix(
+                &[(String::from("A"), bars(&[1.0, 2.0]))],
+                false
+            ))
+        );
+    }
+
+    #[test]
+    fn finds_max_drawdown() {
+        let series = bars(&[100.0, 120.0, 90.0, 110.0, 60.0, 80.0, 125.0, 100.0]);
+        assert_eq!(
+            Some(Drawdown {
+                depth: 0.5,
+                peak: date("2020-01-02"),
+                trough: date("2020-01-05"),
+                recovery: Some(date("2020-01-07")),
+                duration_days: 5,
+            }),
+            tokio_test::block_on(max_drawdown(&series))
+        );
+
+        let series = bars(&[100.0, 80.0, 90.0]);
+        let drawdown = tokio_test::block_on(max_drawdown(&series)).unwrap();
+        assert_eq!(None, drawdown.recovery);
+        assert_eq!(2, drawdown.duration_days);
+
+        assert_eq!(
+            None,
+            tokio_test::block_on(max_drawdown(&bars(&[1.0, 2.0, 3.0])))
+        );
+    }
+}
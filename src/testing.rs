@@ -0,0 +1,46 @@
+//! Fixtures shared by the unit tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+
+use crate::{Interval, StockInfo};
+
+pub(crate) fn date(s: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+}
+
+/// A path in the temporary directory that's unique to this run of the tests,
+/// with anything an earlier run left there removed.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("sstra-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&path);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+/// The info on a symbol over 2020 whose price never moved from
+/// `closing_price`, without any indicators.
+pub(crate) fn info(symbol: &str, closing_price: f64) -> StockInfo {
+    StockInfo {
+        symbol: String::from(symbol),
+        period_start: date("2020-01-01"),
+        period_end: date("2020-12-31"),
+        interval: Interval::Day,
+        currency: None,
+        closing_price,
+        last_close: closing_price,
+        price_difference: 0.0,
+        min: closing_price,
+        max: closing_price,
+        simple_moving_averages: Vec::new(),
+        exponential_moving_averages: Vec::new(),
+        weighted_moving_averages: Vec::new(),
+        relative_strength_index: None,
+        macd: None,
+        bollinger: None,
+        risk: None,
+        benchmark: None,
+    }
+}
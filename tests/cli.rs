@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+/// A directory holding a CSV file of weekday closes for MSFT in early 2024.
+fn prices(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sstra-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut csv = String::from("Date,Open,High,Low,Close,Adj Close,Volume\n");
+    let mut date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    for i in 0..60 {
+        if !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            let close = 370.0 + (i % 7) as f64;
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},1000\n",
+                date,
+                close,
+                close + 1.0,
+                close - 1.0,
+                close,
+                close
+            ));
+        }
+        date += Duration::days(1);
+    }
+    fs::write(dir.join("MSFT.csv"), csv).unwrap();
+    dir
+}
+
+fn sstra(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_main"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn reports_risk_on_daily_bars() {
+    let dir = prices("cli-risk");
+    let data = dir.to_str().unwrap();
+    let output = sstra(&[
+        "--data",
+        data,
+        "--symbols",
+        "MSFT",
+        "--from",
+        "2024-01-02",
+        "--to",
+        "2024-02-28",
+        "--sma",
+        "5",
+        "--risk",
+        "--once",
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut lines = stdout.lines();
+    assert!(lines.next().unwrap().contains("volatility %"));
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("2024-01-02,2024-02-28,MSFT,"));
+
+    let output = sstra(&[
+        "--data",
+        data,
+        "--symbols",
+        "MSFT",
+        "--from",
+        "2024-01-02",
+        "--to",
+        "2024-02-28",
+        "--risk",
+        "--bar-interval",
+        "5m",
+        "--once",
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(
+        "--risk needs daily bars, not 5m ones.\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skips_polling_while_the_market_is_closed() {
+    let dir = prices("cli-closed");
+    // whatever the exchange's date is, it's a holiday
+    let today = chrono::Utc::now().date_naive();
+    let holidays: Vec<String> = (-1..=1)
+        .map(|days| (today + Duration::days(days)).to_string())
+        .collect();
+    let holidays_path = dir.join("holidays.txt");
+    fs::write(&holidays_path, holidays.join("\n")).unwrap();
+    let output = sstra(&[
+        "--data",
+        dir.to_str().unwrap(),
+        "--symbols",
+        "MSFT",
+        "--from",
+        "2024-01-02",
+        "--to",
+        "2024-02-28",
+        "--market-hours",
+        "--holidays",
+        holidays_path.to_str().unwrap(),
+        "--once",
+    ]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        "The market is closed, not polling.\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}